@@ -0,0 +1,380 @@
+use proc_macro::TokenStream;
+use proc_macro2::{Ident, Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{
+	parse_macro_input, parse_quote, Data, DataEnum, DataStruct, DeriveInput, Field, Fields, Index,
+	Member, WhereClause,
+};
+
+/// The name of the `ChangeSet` variant describing a switch to a different variant of an enum.
+const SWITCHED_TO: &str = "SwitchedTo";
+
+/// Derives `Diffable` for a struct or enum.
+///
+/// For structs, a companion `<Name>ChangeSet` struct is generated with the same shape as the
+/// original, where every field `field: T` becomes `field: Diff<T::ChangeSet>`.
+///
+/// For enums, a companion `<Name>ChangeSet` enum is generated, containing a `SwitchedTo(<Name>)`
+/// variant for when the value was changed to a different variant, and a variant with the diffed
+/// fields for every variant of the original enum that has fields, for when the variant stayed the
+/// same but it's fields were changed. Since `SwitchedTo` contains the new value, the enum must
+/// implement `PartialEq`.
+///
+/// Types without any fields or variants use [`core::convert::Infallible`] as their `ChangeSet`, the
+/// same as `()`.
+#[proc_macro_derive(Diffable)]
+pub fn derive_diffable(input: TokenStream) -> TokenStream {
+	let input = parse_macro_input!(input as DeriveInput);
+
+	match input.data {
+		Data::Struct(ref data) => Ok(derive_struct(&input, data)),
+		Data::Enum(ref data) => derive_enum(&input, data),
+		Data::Union(_) => Err(syn::Error::new(
+			Span::call_site(),
+			"#[derive(Diffable)] is not supported on unions",
+		)),
+	}
+	.unwrap_or_else(syn::Error::into_compile_error)
+	.into()
+}
+
+fn derive_struct(input: &DeriveInput, data: &DataStruct) -> TokenStream2 {
+	let ident = &input.ident;
+	let vis = &input.vis;
+	let change_set_ident = format_ident!("{ident}ChangeSet");
+
+	let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+	let where_clause = diffable_where_clause(input, data.fields.iter());
+
+	if data.fields.is_empty() {
+		return infallible_change_set(
+			input,
+			&where_clause,
+			quote!(::change_set::diff::Diff::NotChanged),
+		);
+	}
+
+	let members = members(&data.fields);
+	let change_set_fields = change_set_fields(&data.fields);
+
+	let change_set_def = match data.fields {
+		Fields::Named(_) => quote! {
+			#vis struct #change_set_ident #impl_generics #where_clause {
+				#(#change_set_fields),*
+			}
+		},
+		Fields::Unnamed(_) => quote! {
+			#vis struct #change_set_ident #impl_generics (#(#change_set_fields),*) #where_clause;
+		},
+		Fields::Unit => unreachable!("unit structs have no fields; qed;"),
+	};
+
+	let debug = debug_fields(
+		&change_set_ident,
+		&data.fields,
+		members.iter().map(|member| quote!(&self.#member)),
+	);
+	let doc = format!("The `Diffable::ChangeSet` of [`{ident}`].");
+
+	quote! {
+		#[doc = #doc]
+		#change_set_def
+
+		impl #impl_generics ::core::fmt::Debug for #change_set_ident #ty_generics #where_clause {
+			fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+				#debug
+			}
+		}
+
+		impl #impl_generics ::core::cmp::PartialEq for #change_set_ident #ty_generics #where_clause {
+			fn eq(&self, other: &Self) -> bool {
+				#(self.#members == other.#members)&&*
+			}
+		}
+
+		impl #impl_generics ::change_set::diff::Diffable for #ident #ty_generics #where_clause {
+			type ChangeSet = #change_set_ident #ty_generics;
+
+			fn diff(self, new_value: Self) -> ::change_set::diff::Diff<Self::ChangeSet> {
+				let change_set = #change_set_ident {
+					#(#members: ::change_set::diff::Diffable::diff(self.#members, new_value.#members)),*
+				};
+
+				if #(matches!(change_set.#members, ::change_set::diff::Diff::NotChanged))&&* {
+					::change_set::diff::Diff::NotChanged
+				} else {
+					::change_set::diff::Diff::ChangedTo(change_set)
+				}
+			}
+		}
+	}
+}
+
+fn derive_enum(input: &DeriveInput, data: &DataEnum) -> syn::Result<TokenStream2> {
+	let ident = &input.ident;
+	let vis = &input.vis;
+	let change_set_ident = format_ident!("{ident}ChangeSet");
+	let switched_to = Ident::new(SWITCHED_TO, Span::call_site());
+
+	let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+	let mut where_clause =
+		diffable_where_clause(input, data.variants.iter().flat_map(|variant| &variant.fields));
+
+	if data.variants.is_empty() {
+		return Ok(infallible_change_set(input, &where_clause, quote!(match self {})));
+	}
+
+	// the change set contains the new value if the variant was switched
+	where_clause
+		.predicates
+		.push(parse_quote!(#ident #ty_generics: ::core::cmp::PartialEq));
+
+	// variants without any fields can't change without switching to another variant
+	let (changeable_variants, fieldless_variants) = data
+		.variants
+		.iter()
+		.partition::<Vec<_>, _>(|variant| !variant.fields.is_empty());
+
+	if let Some(variant) = changeable_variants.iter().find(|variant| variant.ident == SWITCHED_TO) {
+		return Err(syn::Error::new(
+			variant.ident.span(),
+			format!(
+				"variants named `{SWITCHED_TO}` must not contain any fields, as the name is used by \
+				 the generated `{change_set_ident}`"
+			),
+		));
+	}
+
+	let variant_defs = changeable_variants.iter().map(|variant| {
+		let variant_ident = &variant.ident;
+		let docs = variant.attrs.iter().filter(|attr| attr.path.is_ident("doc"));
+		let change_set_fields = change_set_fields(&variant.fields);
+
+		match variant.fields {
+			Fields::Named(_) => quote!(#(#docs)* #variant_ident { #(#change_set_fields),* }),
+			_ => quote!(#(#docs)* #variant_ident(#(#change_set_fields),*)),
+		}
+	});
+
+	let debug_arms = changeable_variants.iter().map(|variant| {
+		let variant_ident = &variant.ident;
+		let pattern = variant_pattern(&variant.fields, "value");
+		let debug = debug_fields(
+			variant_ident,
+			&variant.fields,
+			bindings(&variant.fields, "value").into_iter().map(|binding| quote!(#binding)),
+		);
+
+		quote!(Self::#variant_ident #pattern => #debug)
+	});
+
+	let eq_arms = changeable_variants.iter().map(|variant| {
+		let variant_ident = &variant.ident;
+		let (old_pattern, new_pattern) =
+			(variant_pattern(&variant.fields, "old"), variant_pattern(&variant.fields, "new"));
+		let (old_bindings, new_bindings) =
+			(bindings(&variant.fields, "old"), bindings(&variant.fields, "new"));
+
+		quote! {
+			(Self::#variant_ident #old_pattern, Self::#variant_ident #new_pattern) => {
+				#(#old_bindings == #new_bindings)&&*
+			}
+		}
+	});
+
+	let diff_arms = changeable_variants.iter().map(|variant| {
+		let variant_ident = &variant.ident;
+		let (old_pattern, new_pattern, diff_pattern) = (
+			variant_pattern(&variant.fields, "old"),
+			variant_pattern(&variant.fields, "new"),
+			variant_pattern(&variant.fields, "diff"),
+		);
+		let (old_bindings, new_bindings, diff_bindings) = (
+			bindings(&variant.fields, "old"),
+			bindings(&variant.fields, "new"),
+			bindings(&variant.fields, "diff"),
+		);
+
+		quote! {
+			(Self::#variant_ident #old_pattern, Self::#variant_ident #new_pattern) => {
+				#(let #diff_bindings = ::change_set::diff::Diffable::diff(#old_bindings, #new_bindings);)*
+
+				if #(matches!(#diff_bindings, ::change_set::diff::Diff::NotChanged))&&* {
+					::change_set::diff::Diff::NotChanged
+				} else {
+					::change_set::diff::Diff::ChangedTo(#change_set_ident::#variant_ident #diff_pattern)
+				}
+			}
+		}
+	});
+
+	let unchanged_arms = fieldless_variants.iter().map(|variant| {
+		let variant_ident = &variant.ident;
+		let pattern = variant_pattern(&variant.fields, "value");
+
+		quote! {
+			(Self::#variant_ident #pattern, Self::#variant_ident #pattern) => {
+				::change_set::diff::Diff::NotChanged
+			}
+		}
+	});
+
+	let doc = format!("The `Diffable::ChangeSet` of [`{ident}`].");
+	let switched_to_doc = format!(
+		"The value was switched to a different variant of [`{ident}`]. Contained is the new value, \
+		 as there is nothing to diff it against."
+	);
+
+	Ok(quote! {
+		#[doc = #doc]
+		#vis enum #change_set_ident #impl_generics #where_clause {
+			#[doc = #switched_to_doc]
+			#switched_to(#ident #ty_generics),
+			#(#variant_defs,)*
+		}
+
+		impl #impl_generics ::core::fmt::Debug for #change_set_ident #ty_generics #where_clause {
+			fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+				match self {
+					Self::#switched_to(value) => {
+						f.debug_tuple(stringify!(#switched_to)).field(value).finish()
+					},
+					#(#debug_arms,)*
+				}
+			}
+		}
+
+		impl #impl_generics ::core::cmp::PartialEq for #change_set_ident #ty_generics #where_clause {
+			fn eq(&self, other: &Self) -> bool {
+				#[allow(unreachable_patterns)]
+				match (self, other) {
+					(Self::#switched_to(old), Self::#switched_to(new)) => old == new,
+					#(#eq_arms,)*
+					_ => false,
+				}
+			}
+		}
+
+		impl #impl_generics ::change_set::diff::Diffable for #ident #ty_generics #where_clause {
+			type ChangeSet = #change_set_ident #ty_generics;
+
+			fn diff(self, new_value: Self) -> ::change_set::diff::Diff<Self::ChangeSet> {
+				#[allow(unreachable_patterns)]
+				match (self, new_value) {
+					#(#diff_arms,)*
+					#(#unchanged_arms,)*
+					(_, new_value) => ::change_set::diff::Diff::ChangedTo(
+						#change_set_ident::#switched_to(new_value)
+					),
+				}
+			}
+		}
+	})
+}
+
+/// Implements `Diffable` with [`core::convert::Infallible`] as the `ChangeSet`, for types that can
+/// never change.
+fn infallible_change_set(
+	input: &DeriveInput,
+	where_clause: &WhereClause,
+	body: TokenStream2,
+) -> TokenStream2 {
+	let ident = &input.ident;
+	let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+
+	quote! {
+		impl #impl_generics ::change_set::diff::Diffable for #ident #ty_generics #where_clause {
+			type ChangeSet = ::core::convert::Infallible;
+
+			fn diff(self, _: Self) -> ::change_set::diff::Diff<Self::ChangeSet> {
+				#body
+			}
+		}
+	}
+}
+
+/// The where clause of the input, with an additional `Diffable` bound for every field type.
+fn diffable_where_clause<'a>(
+	input: &DeriveInput,
+	fields: impl Iterator<Item = &'a Field>,
+) -> WhereClause {
+	let mut where_clause =
+		input.generics.where_clause.clone().unwrap_or_else(|| parse_quote!(where));
+
+	where_clause.predicates.extend(
+		fields
+			.map(|field| &field.ty)
+			.map(|ty| -> syn::WherePredicate { parse_quote!(#ty: ::change_set::diff::Diffable) }),
+	);
+
+	where_clause
+}
+
+/// The fields of the `ChangeSet`, where every field `field: T` becomes `field: Diff<T::ChangeSet>`.
+/// Doc comments on the fields are preserved.
+fn change_set_fields(fields: &Fields) -> Vec<TokenStream2> {
+	fields
+		.iter()
+		.map(|field| {
+			let (vis, ident, colon, ty) = (&field.vis, &field.ident, &field.colon_token, &field.ty);
+			let docs = field.attrs.iter().filter(|attr| attr.path.is_ident("doc"));
+
+			quote! {
+				#(#docs)*
+				#vis #ident #colon ::change_set::diff::Diff<<#ty as ::change_set::diff::Diffable>::ChangeSet>
+			}
+		})
+		.collect()
+}
+
+/// The `Debug` implementation for a set of fields named `name`, with `values` being the expressions
+/// used to access the fields.
+fn debug_fields(
+	name: &Ident,
+	fields: &Fields,
+	values: impl Iterator<Item = TokenStream2>,
+) -> TokenStream2 {
+	let builder = match fields {
+		Fields::Named(_) => quote!(debug_struct),
+		_ => quote!(debug_tuple),
+	};
+
+	let fields = fields.iter().zip(values).map(|(field, value)| match &field.ident {
+		Some(field_ident) => quote!(.field(stringify!(#field_ident), #value)),
+		None => quote!(.field(#value)),
+	});
+
+	quote!(f.#builder(stringify!(#name)) #(#fields)* .finish())
+}
+
+/// The members (`self.<member>`) of the provided fields.
+fn members(fields: &Fields) -> Vec<Member> {
+	fields
+		.iter()
+		.enumerate()
+		.map(|(i, field)| match &field.ident {
+			Some(ident) => Member::Named(ident.clone()),
+			None => Member::Unnamed(Index { index: i as u32, span: Span::call_site() }),
+		})
+		.collect()
+}
+
+/// Local bindings for the provided fields, named `<prefix>_<index>`.
+fn bindings(fields: &Fields, prefix: &str) -> Vec<Ident> {
+	(0..fields.len()).map(|i| format_ident!("{prefix}_{i}")).collect()
+}
+
+/// A pattern destructuring a variant with the provided fields into the bindings created by
+/// [`bindings`].
+fn variant_pattern(fields: &Fields, prefix: &str) -> TokenStream2 {
+	let bindings = bindings(fields, prefix);
+
+	match fields {
+		Fields::Named(_) => {
+			let members = members(fields);
+			quote!({ #(#members: #bindings),* })
+		},
+		Fields::Unnamed(_) => quote!((#(#bindings),*)),
+		Fields::Unit => quote!(),
+	}
+}
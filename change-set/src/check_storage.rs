@@ -1,22 +1,113 @@
-use core::fmt::Debug;
+use core::{
+	fmt::{self, Debug, Display},
+	marker::PhantomData,
+};
 
 use frame_support::{
-	pallet_prelude::{StorageDoubleMap, StorageMap, StorageValue},
-	storage::types::QueryKindTrait,
+	pallet_prelude::{CountedStorageMap, StorageDoubleMap, StorageMap, StorageNMap, StorageValue},
+	storage::{
+		storage_prefix,
+		types::{
+			CountedStorageMapInstance, EncodeLikeTuple, KeyGenerator, QueryKindTrait,
+			ReversibleKeyGenerator, TupleToEncodedIter,
+		},
+		PrefixIterator, StoragePrefixedMap,
+	},
 	traits::{Get, StorageInstance},
 	ReversibleStorageHasher, StorageHasher,
 };
-use parity_scale_codec::FullCodec;
-use sp_std::collections::btree_map::BTreeMap;
+use parity_scale_codec::{Decode, EncodeLike, FullCodec};
+use sp_core::hexdisplay::HexDisplay;
+use sp_std::{collections::btree_map::BTreeMap, vec::Vec};
+
+use crate::{
+	diff::{Diff, Diffable, IntoDelta, MapValueDiff},
+	error::{StorageAssertionError, StorageAssertionErrorKind},
+	recorder::RawChange,
+};
+
+/// Identifies a storage by it's pallet and storage prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StorageId {
+	/// The pallet prefix, i.e. the name of the pallet in `construct_runtime!`.
+	pub pallet: &'static str,
+	/// The storage prefix, i.e. the name of the storage item.
+	pub storage: &'static str,
+}
+
+impl StorageId {
+	/// The [`StorageId`] of the storage with the provided [`StorageInstance`] prefix.
+	#[must_use]
+	pub fn of<Prefix: StorageInstance>() -> Self {
+		Self { pallet: Prefix::pallet_prefix(), storage: Prefix::STORAGE_PREFIX }
+	}
+
+	/// The raw key prefix of the storage, `twox128(pallet) ++ twox128(storage)`.
+	#[must_use]
+	pub fn prefix(&self) -> [u8; 32] {
+		storage_prefix(self.pallet.as_bytes(), self.storage.as_bytes())
+	}
+}
 
-use crate::diff::{Diff, Diffable};
+impl Display for StorageId {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}/{}", self.pallet, self.storage)
+	}
+}
+
+/// A storage's own count of it's entries, alongside the number of entries actually in the storage.
+/// See [`CheckStorage::counter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Counter {
+	/// The value of the counter.
+	pub counted: u32,
+	/// The number of entries in the storage.
+	pub entries: usize,
+}
+
+impl Counter {
+	/// How much the counter and the number of entries changed by from `self` to `after`.
+	#[must_use]
+	pub fn change_to(self, after: Self) -> CounterChange {
+		let entries = |counter: Self| i64::try_from(counter.entries).unwrap_or(i64::MAX);
+
+		CounterChange {
+			counted: i64::from(after.counted) - i64::from(self.counted),
+			entries: entries(after) - entries(self),
+		}
+	}
+}
+
+/// How much a storage's [`Counter`] changed by over the action. See
+/// [`CheckStorage::counter_change`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CounterChange {
+	/// The change of the counter.
+	pub counted: i64,
+	/// The change of the number of entries in the storage.
+	pub entries: i64,
+}
+
+impl CounterChange {
+	/// The [`CounterDrift`](StorageAssertionErrorKind::CounterDrift) of `storage` if the counter
+	/// didn't change by as much as the number of entries did. Drift that was already there before
+	/// the action isn't blamed on the action.
+	pub(crate) fn drift(self, storage: StorageId) -> Option<StorageAssertionError> {
+		(self.counted != self.entries).then(|| StorageAssertionError {
+			storage,
+			kind: StorageAssertionErrorKind::CounterDrift,
+			expected: Some(Box::new(self.entries)),
+			found: Some(Box::new(self.counted)),
+		})
+	}
+}
 
 pub trait CheckStorage {
 	type Value: Diffable;
 
 	// const NAME: &'static str;
 
-	fn name() -> (&'static str, &'static str);
+	fn name() -> StorageId;
 
 	fn current_value() -> Self::Value;
 
@@ -25,6 +116,166 @@ pub trait CheckStorage {
 	) -> Diff<<Self::Value as Diffable>::ChangeSet> {
 		expected.diff(Self::current_value())
 	}
+
+	/// The current [`Counter`] of storages that keep a separate count of their entries, such as
+	/// [`CountedStorageMap`]. This is read before and after the action, and if the counter didn't
+	/// change by as much as the number of entries did, it's reported as a
+	/// [`CounterDrift`](crate::error::StorageAssertionErrorKind::CounterDrift).
+	#[must_use]
+	fn counter() -> Option<Counter> {
+		None
+	}
+
+	/// The change of the [`Counter`] of this storage over the raw `changes` of the action, found
+	/// from the written counter and the inserted and removed entries. Used instead of reading
+	/// [`Self::counter`] before and after the action when the writes of the action are
+	/// [recorded](crate::recorder). Defaults to none, as for [`Self::counter`].
+	#[must_use]
+	fn counter_change(changes: &[RawChange]) -> Option<CounterChange> {
+		let _ = changes;
+		None
+	}
+
+	/// The raw key prefixes that this storage is stored under, used to attribute written keys to
+	/// this storage in [strict mode](crate::AssertableDiffableStorageAction::strict). Defaults to
+	/// the prefix of [`Self::name`].
+	#[must_use]
+	fn prefixes() -> Vec<Vec<u8>> {
+		vec![Self::name().prefix().to_vec()]
+	}
+
+	/// The values of this storage from before and after the action, decoded from the `changes`
+	/// recorded during the action instead of reading [`Self::current_value`] before and after the
+	/// action when the writes of the action are [recorded](crate::recorder). `None` if the storage
+	/// wasn't written to.
+	///
+	/// Maps only decode the written entries, as entries that weren't written to are the same before
+	/// and after the action and as such never show up in the diff. This must not write to the
+	/// storage, and should avoid reading it, so that checks scale with the amount of changes.
+	#[must_use]
+	fn recorded_values(changes: &[RawChange]) -> Option<(Self::Value, Self::Value)>;
+
+	/// The diff of this storage over the `changes` recorded during the action. See
+	/// [`Self::recorded_values`].
+	#[must_use]
+	fn diff_recorded_changes(changes: &[RawChange]) -> Diff<<Self::Value as Diffable>::ChangeSet> {
+		Self::recorded_values(changes)
+			.map_or(Diff::NotChanged, |(before, after)| before.diff(after))
+	}
+
+	/// The entries of this storage that can't be decoded after the action. The iterators of maps
+	/// skip these entries, so they'd otherwise be invisible to [`Self::current_value`]. If the
+	/// `changes` of the action were recorded, only the written entries are checked.
+	///
+	/// Each of these is reported as
+	/// [`Undecodable`](crate::error::StorageAssertionErrorKind::Undecodable). Defaults to none.
+	#[must_use]
+	fn undecodable_entries(changes: Option<&[RawChange]>) -> Vec<UndecodableEntry> {
+		let _ = changes;
+		Vec::new()
+	}
+}
+
+/// A raw entry of a storage that couldn't be decoded. See [`CheckStorage::undecodable_entries`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UndecodableEntry {
+	pub key: OpaqueKey,
+	pub value: OpaqueValue,
+}
+
+impl UndecodableEntry {
+	pub(crate) fn at(self, storage: StorageId) -> StorageAssertionError {
+		StorageAssertionError {
+			storage,
+			kind: StorageAssertionErrorKind::Undecodable,
+			expected: None,
+			found: Some(Box::new(self)),
+		}
+	}
+}
+
+/// The entries under `prefix` that `decodes` (which is passed the key without the prefix and the
+/// value) returns false for. If `changes` are provided, only the entries written to in `changes`
+/// are checked instead of the entire prefix.
+fn undecodable_under(
+	prefix: [u8; 32],
+	changes: Option<&[RawChange]>,
+	decodes: fn(&[u8], &[u8]) -> bool,
+) -> Vec<UndecodableEntry> {
+	let entries = match changes {
+		Some(changes) => changes
+			.iter()
+			.filter_map(|change| Some((change.key_under(&prefix)?.to_vec(), change.after.clone()?)))
+			.collect(),
+		None => PrefixIterator::<(Vec<u8>, Vec<u8>)>::new(
+			prefix.to_vec(),
+			prefix.to_vec(),
+			|key, value| Ok((key.to_vec(), value.to_vec())),
+		)
+		.collect::<Vec<_>>(),
+	};
+
+	entries
+		.into_iter()
+		.filter(|(key, value)| !decodes(key, value))
+		.map(|(key, value)| UndecodableEntry { key: OpaqueKey(key), value: OpaqueValue(value) })
+		.collect()
+}
+
+/// The entries of a map with the entries under `prefix` from before and after the `changes`,
+/// decoding only the written entries with `decode` (which is passed the key without the prefix and
+/// the value). Entries that weren't changed never show up in the diff of a map, so diffing these is
+/// the same as diffing the entire map.
+pub(crate) fn written_entries<Key, Value>(
+	changes: &[RawChange],
+	prefix: &[u8],
+	decode: impl Fn(&[u8], &[u8]) -> Option<(Key, Value)>,
+) -> (BTreeMap<Key, Value>, BTreeMap<Key, Value>)
+where
+	Key: Ord,
+{
+	let mut before = BTreeMap::new();
+	let mut after = BTreeMap::new();
+
+	for (key, change) in
+		changes.iter().filter_map(|change| Some((change.key_under(prefix)?, change)))
+	{
+		before.extend(change.before.as_deref().and_then(|value| decode(key, value)));
+		after.extend(change.after.as_deref().and_then(|value| decode(key, value)));
+	}
+
+	(before, after)
+}
+
+/// The recorded change of the value at exactly `key`, if it was written to.
+fn change_at<'a>(changes: &'a [RawChange], key: &[u8]) -> Option<&'a RawChange> {
+	changes.iter().find(|change| change.child_trie.is_none() && change.key == key)
+}
+
+/// The recorded `raw` value decoded as a `Value`, or `None` if there's no value or it can't be
+/// decoded, the same as reading it from the storage.
+fn decode_value<Value: Decode>(raw: Option<&[u8]>) -> Option<Value> {
+	raw.and_then(|mut raw| Value::decode(&mut raw).ok())
+}
+
+/// Decodes an entry of a [`StorageDoubleMap`] from it's key without the prefix of the map and it's
+/// value.
+fn decode_double_map_entry<Hasher1, Key1, Hasher2, Key2, Value>(
+	key: &[u8],
+	mut value: &[u8],
+) -> Option<((Key1, Key2), Value)>
+where
+	Hasher1: ReversibleStorageHasher,
+	Key1: Decode,
+	Hasher2: ReversibleStorageHasher,
+	Key2: Decode,
+	Value: Decode,
+{
+	let mut key2 = Hasher1::reverse(key);
+	let k1 = Key1::decode(&mut key2).ok()?;
+	let k2 = Key2::decode(&mut Hasher2::reverse(key2)).ok()?;
+
+	Some(((k1, k2), Value::decode(&mut value).ok()?))
 }
 
 impl<Prefix, Value, QueryKind, OnEmpty> CheckStorage
@@ -40,13 +291,20 @@ where
 
 	// const NAME: &'static str = Prefix::STORAGE_PREFIX;
 
-	fn name() -> (&'static str, &'static str) {
-		(Prefix::pallet_prefix(), Prefix::STORAGE_PREFIX)
+	fn name() -> StorageId {
+		StorageId::of::<Prefix>()
 	}
 
 	fn current_value() -> Self::Value {
 		Self::get()
 	}
+
+	fn recorded_values(changes: &[RawChange]) -> Option<(Self::Value, Self::Value)> {
+		let change = change_at(changes, &Self::name().prefix())?;
+		let query = |raw| QueryKind::from_optional_value_to_query(decode_value(raw));
+
+		Some((query(change.before.as_deref()), query(change.after.as_deref())))
+	}
 }
 
 impl<Prefix, Hasher, Key, Value, QueryKind, OnEmpty, MaxValues> CheckStorage
@@ -63,13 +321,25 @@ where
 {
 	type Value = BTreeMap<Key, Value>;
 
-	fn name() -> (&'static str, &'static str) {
-		(Prefix::pallet_prefix(), Prefix::STORAGE_PREFIX)
+	fn name() -> StorageId {
+		StorageId::of::<Prefix>()
 	}
 
 	fn current_value() -> Self::Value {
 		Self::iter().collect::<BTreeMap<_, _>>()
 	}
+
+	fn recorded_values(changes: &[RawChange]) -> Option<(Self::Value, Self::Value)> {
+		Some(written_entries(changes, &Self::name().prefix(), |key, mut value| {
+			Some((Key::decode(&mut Hasher::reverse(key)).ok()?, Value::decode(&mut value).ok()?))
+		}))
+	}
+
+	fn undecodable_entries(changes: Option<&[RawChange]>) -> Vec<UndecodableEntry> {
+		undecodable_under(Self::name().prefix(), changes, |key, mut value| {
+			Key::decode(&mut Hasher::reverse(key)).is_ok() && Value::decode(&mut value).is_ok()
+		})
+	}
 }
 
 impl<Prefix, Hasher1, Key1, Hasher2, Key2, Value, QueryKind, OnEmpty, MaxValues> CheckStorage
@@ -85,21 +355,808 @@ where
 	OnEmpty: Get<QueryKind::Query> + 'static,
 	MaxValues: Get<Option<u32>>,
 {
-	// REVIEW(benluelo): Would `BTreeMap<(Key1, Key2)>, Value>` have better UX?
+	/// The entries are nested by their first key, so that all of the entries under a first key can
+	/// be asserted on together. See [`Flat`] for keying the entries by both keys instead.
+	///
+	/// A first key is never reported as [`Added`](MapValueDiff::Added) or
+	/// [`Missing`](MapValueDiff::Missing), but as [`Changed`](MapValueDiff::Changed) with the diff
+	/// of it's entries, as if it always had an empty map of entries. Recorded writes don't tell
+	/// whether or not a first key has any other entries, so this keeps the diffs the same with and
+	/// without [recording](crate::recorder) without reading the storage.
 	type Value = BTreeMap<Key1, BTreeMap<Key2, Value>>;
 
-	fn name() -> (&'static str, &'static str) {
-		(Prefix::pallet_prefix(), Prefix::STORAGE_PREFIX)
+	fn name() -> StorageId {
+		StorageId::of::<Prefix>()
 	}
 
 	fn current_value() -> Self::Value {
 		let mut found_map = BTreeMap::new();
 
 		for (k1, k2, v) in Self::iter() {
-			dbg!(&k1, &k2);
 			found_map.entry(k1).or_insert_with(BTreeMap::<Key2, Value>::new).insert(k2, v);
 		}
 
 		found_map
 	}
+
+	fn diff_storage_changes_with_expected_changes(
+		expected: Self::Value,
+	) -> Diff<<Self::Value as Diffable>::ChangeSet> {
+		diff_by_first_key(expected, Self::current_value())
+	}
+
+	/// Only the written entries are decoded, nested by their first key.
+	fn recorded_values(changes: &[RawChange]) -> Option<(Self::Value, Self::Value)> {
+		let (before, after) = Flat::<Self>::recorded_values(changes)?;
+
+		let nest = |entries: BTreeMap<(Key1, Key2), Value>| {
+			let mut nested = BTreeMap::<Key1, BTreeMap<Key2, Value>>::new();
+
+			for ((k1, k2), v) in entries {
+				nested.entry(k1).or_default().insert(k2, v);
+			}
+
+			nested
+		};
+
+		Some((nest(before), nest(after)))
+	}
+
+	fn diff_recorded_changes(changes: &[RawChange]) -> Diff<<Self::Value as Diffable>::ChangeSet> {
+		Self::recorded_values(changes)
+			.map_or(Diff::NotChanged, |(before, after)| diff_by_first_key(before, after))
+	}
+
+	fn undecodable_entries(changes: Option<&[RawChange]>) -> Vec<UndecodableEntry> {
+		undecodable_under(Self::name().prefix(), changes, |key, value| {
+			decode_double_map_entry::<Hasher1, Key1, Hasher2, Key2, Value>(key, value).is_some()
+		})
+	}
+}
+
+/// Diffs the entries of a nested [`StorageDoubleMap`] by their first key, with a first key that's
+/// only on one side diffed against an empty map of entries.
+fn diff_by_first_key<Key1, Key2, Value>(
+	before: BTreeMap<Key1, BTreeMap<Key2, Value>>,
+	mut after: BTreeMap<Key1, BTreeMap<Key2, Value>>,
+) -> Diff<BTreeMap<Key1, MapValueDiff<BTreeMap<Key2, Value>>>>
+where
+	Key1: Ord + Debug,
+	Key2: Ord + Debug,
+	Value: PartialEq + Debug + Diffable,
+{
+	let mut map = before
+		.into_iter()
+		.map(|(k1, entries)| {
+			let updated = after.remove(&k1).unwrap_or_default();
+			(k1, entries.diff(updated))
+		})
+		.collect::<Vec<_>>();
+
+	map.extend(after.into_iter().map(|(k1, entries)| (k1, BTreeMap::new().diff(entries))));
+
+	let map = map
+		.into_iter()
+		.filter_map(|(k1, diff)| match diff {
+			Diff::NotChanged => None,
+			Diff::ChangedTo(changed) => Some((k1, MapValueDiff::Changed(changed))),
+		})
+		.collect::<BTreeMap<_, _>>();
+
+	if map.is_empty() {
+		Diff::NotChanged
+	} else {
+		Diff::ChangedTo(map)
+	}
+}
+
+/// The [`CounterChange`] of the [`CountedStorageMap`] with the prefix `Prefix` over the `changes`,
+/// from the written counter and the entries that were inserted or removed.
+fn counted_map_counter_change<Prefix: CountedStorageMapInstance>(
+	changes: &[RawChange],
+) -> CounterChange {
+	let prefix = StorageId::of::<Prefix>().prefix();
+	let counter_key = StorageId::of::<Prefix::CounterPrefix>().prefix();
+	let counted = |value: &Option<Vec<u8>>| {
+		value
+			.as_deref()
+			.and_then(|mut raw| u32::decode(&mut raw).ok())
+			.map_or(0, i64::from)
+	};
+
+	let mut change = CounterChange::default();
+
+	for written in changes {
+		if written.child_trie.is_none() && written.key == counter_key {
+			change.counted = counted(&written.after) - counted(&written.before);
+		} else if written.key_under(&prefix).is_some() {
+			change.entries +=
+				i64::from(written.after.is_some()) - i64::from(written.before.is_some());
+		}
+	}
+
+	change
+}
+
+/// In addition to the entries of the map, the hidden counter storage is checked to change
+/// consistently with the number of entries.
+impl<Prefix, Hasher, Key, Value, QueryKind, OnEmpty, MaxValues> CheckStorage
+	for CountedStorageMap<Prefix, Hasher, Key, Value, QueryKind, OnEmpty, MaxValues>
+where
+	Prefix: CountedStorageMapInstance,
+	Hasher: StorageHasher + ReversibleStorageHasher,
+	Key: FullCodec + Debug + Ord,
+	Value: FullCodec + PartialEq + Diffable,
+	QueryKind: QueryKindTrait<Value, OnEmpty>,
+	OnEmpty: Get<QueryKind::Query> + 'static,
+	MaxValues: Get<Option<u32>>,
+{
+	type Value = BTreeMap<Key, Value>;
+
+	fn name() -> StorageId {
+		StorageId::of::<Prefix>()
+	}
+
+	fn current_value() -> Self::Value {
+		Self::iter().collect::<BTreeMap<_, _>>()
+	}
+
+	fn counter() -> Option<Counter> {
+		Some(Counter { counted: Self::count(), entries: Self::iter().count() })
+	}
+
+	fn counter_change(changes: &[RawChange]) -> Option<CounterChange> {
+		Some(counted_map_counter_change::<Prefix>(changes))
+	}
+
+	fn recorded_values(changes: &[RawChange]) -> Option<(Self::Value, Self::Value)> {
+		Some(written_entries(changes, &Self::name().prefix(), |key, mut value| {
+			Some((Key::decode(&mut Hasher::reverse(key)).ok()?, Value::decode(&mut value).ok()?))
+		}))
+	}
+
+	fn undecodable_entries(changes: Option<&[RawChange]>) -> Vec<UndecodableEntry> {
+		undecodable_under(Self::name().prefix(), changes, |key, mut value| {
+			Key::decode(&mut Hasher::reverse(key)).is_ok() && Value::decode(&mut value).is_ok()
+		})
+	}
+
+	fn prefixes() -> Vec<Vec<u8>> {
+		vec![
+			Self::name().prefix().to_vec(),
+			StorageId::of::<Prefix::CounterPrefix>().prefix().to_vec(),
+		]
+	}
+}
+
+/// Maps whose entries can be read by key, without iterating over the entire map. See
+/// [`AssertableDiffableStorageAction::check_storage_keys`](crate::AssertableDiffableStorageAction::check_storage_keys).
+pub trait CheckStorageKeys: CheckStorage<Value = BTreeMap<Self::Key, Self::Entry>> {
+	type Key: Ord + Clone + Debug + 'static;
+	type Entry: PartialEq + Debug + Diffable + 'static;
+
+	/// The entry at `key`, if there is one.
+	fn entry(key: &Self::Key) -> Option<Self::Entry>;
+
+	/// The raw storage key of the entry at `key`.
+	fn hashed_key(key: &Self::Key) -> Vec<u8>;
+}
+
+impl<Prefix, Hasher, Key, Value, QueryKind, OnEmpty, MaxValues> CheckStorageKeys
+	for StorageMap<Prefix, Hasher, Key, Value, QueryKind, OnEmpty, MaxValues>
+where
+	Prefix: StorageInstance,
+	Hasher: StorageHasher + ReversibleStorageHasher,
+	Key: FullCodec + Debug + Ord + Clone + 'static,
+	Value: FullCodec + PartialEq + Diffable + 'static,
+	QueryKind: QueryKindTrait<Value, OnEmpty>,
+	QueryKind::Query: Diffable,
+	OnEmpty: Get<QueryKind::Query> + 'static,
+	MaxValues: Get<Option<u32>>,
+{
+	type Entry = Value;
+	type Key = Key;
+
+	fn entry(key: &Key) -> Option<Value> {
+		Self::try_get(key).ok()
+	}
+
+	fn hashed_key(key: &Key) -> Vec<u8> {
+		Self::hashed_key_for(key)
+	}
+}
+
+impl<Prefix, Hasher, Key, Value, QueryKind, OnEmpty, MaxValues> CheckStorageKeys
+	for CountedStorageMap<Prefix, Hasher, Key, Value, QueryKind, OnEmpty, MaxValues>
+where
+	Prefix: CountedStorageMapInstance,
+	Hasher: StorageHasher + ReversibleStorageHasher,
+	Key: FullCodec + Debug + Ord + Clone + 'static,
+	Value: FullCodec + PartialEq + Diffable + 'static,
+	QueryKind: QueryKindTrait<Value, OnEmpty>,
+	OnEmpty: Get<QueryKind::Query> + 'static,
+	MaxValues: Get<Option<u32>>,
+{
+	type Entry = Value;
+	type Key = Key;
+
+	fn entry(key: &Key) -> Option<Value> {
+		Self::try_get(key).ok()
+	}
+
+	fn hashed_key(key: &Key) -> Vec<u8> {
+		Self::hashed_key_for(key)
+	}
+}
+
+/// The entries are keyed by the full key tuple, i.e. `(Key1, Key2, Key3)` for a `StorageNMap` with
+/// three keys.
+impl<Prefix, Key, Value, QueryKind, OnEmpty, MaxValues> CheckStorage
+	for StorageNMap<Prefix, Key, Value, QueryKind, OnEmpty, MaxValues>
+where
+	Prefix: StorageInstance,
+	Key: ReversibleKeyGenerator,
+	<Key as KeyGenerator>::Key: Debug + Ord,
+	Value: FullCodec + PartialEq + Diffable,
+	QueryKind: QueryKindTrait<Value, OnEmpty>,
+	OnEmpty: Get<QueryKind::Query> + 'static,
+	MaxValues: Get<Option<u32>>,
+{
+	type Value = BTreeMap<<Key as KeyGenerator>::Key, Value>;
+
+	fn name() -> StorageId {
+		StorageId::of::<Prefix>()
+	}
+
+	fn current_value() -> Self::Value {
+		Self::iter().collect::<BTreeMap<_, _>>()
+	}
+
+	fn recorded_values(changes: &[RawChange]) -> Option<(Self::Value, Self::Value)> {
+		Some(written_entries(changes, &Self::name().prefix(), |key, mut value| {
+			Some((Key::decode_final_key(key).ok()?.0, Value::decode(&mut value).ok()?))
+		}))
+	}
+
+	fn undecodable_entries(changes: Option<&[RawChange]>) -> Vec<UndecodableEntry> {
+		undecodable_under(Self::name().prefix(), changes, |key, mut value| {
+			Key::decode_final_key(key).is_ok() && Value::decode(&mut value).is_ok()
+		})
+	}
+}
+
+/// Checks the storage `S`, with all of it's numeric values diffed by their
+/// [`Delta`](crate::diff::Delta) instead of by their new value. See [`IntoDelta`] for the supported
+/// value types.
+///
+/// ```rust,ignore
+/// do_action::<(AsDelta<TotalIssuance<T>>, ()), _, _>(|| { ... })
+///     .check_storage::<AsDelta<TotalIssuance<T>>, _>(Delta::Increased(100))
+///     .assert_storage_changes();
+/// ```
+pub struct AsDelta<S>(PhantomData<S>);
+
+impl<S> CheckStorage for AsDelta<S>
+where
+	S: CheckStorage,
+	S::Value: IntoDelta,
+{
+	type Value = <S::Value as IntoDelta>::Output;
+
+	fn name() -> StorageId {
+		S::name()
+	}
+
+	fn current_value() -> Self::Value {
+		S::current_value().into_delta()
+	}
+
+	fn recorded_values(changes: &[RawChange]) -> Option<(Self::Value, Self::Value)> {
+		S::recorded_values(changes).map(|(before, after)| (before.into_delta(), after.into_delta()))
+	}
+
+	fn counter() -> Option<Counter> {
+		S::counter()
+	}
+
+	fn counter_change(changes: &[RawChange]) -> Option<CounterChange> {
+		S::counter_change(changes)
+	}
+
+	fn prefixes() -> Vec<Vec<u8>> {
+		S::prefixes()
+	}
+
+	fn undecodable_entries(changes: Option<&[RawChange]>) -> Vec<UndecodableEntry> {
+		S::undecodable_entries(changes)
+	}
+}
+
+/// Checks the [`StorageValue`] `S` by whether or not it's key exists, as `Some(value)` if it does
+/// and `None` if it doesn't, regardless of it's `QueryKind`.
+///
+/// For `ValueQuery` storages, [`StorageValue::get`] returns the default value when the key doesn't
+/// exist, so killing the storage and setting it to the default value can't be told apart without
+/// this:
+///
+/// ```rust,ignore
+/// do_action::<(AsOption<Something<T>>, ()), _, _>(|| { ... })
+///     .check_storage::<AsOption<Something<T>>, _>(OptionDiff::WasSomeNowNone)
+///     .assert_storage_changes();
+/// ```
+///
+/// Maps don't need this, as their entries are read by iterating over the existing keys.
+pub struct AsOption<S>(PhantomData<S>);
+
+impl<Prefix, Value, QueryKind, OnEmpty> CheckStorage
+	for AsOption<StorageValue<Prefix, Value, QueryKind, OnEmpty>>
+where
+	Prefix: StorageInstance,
+	Value: FullCodec + PartialEq + Eq + Diffable,
+	QueryKind: QueryKindTrait<Value, OnEmpty>,
+	OnEmpty: Get<QueryKind::Query> + 'static,
+{
+	type Value = Option<Value>;
+
+	fn name() -> StorageId {
+		StorageId::of::<Prefix>()
+	}
+
+	/// The decoded value, if the key exists. A value that can't be decoded is reported by
+	/// [`Self::undecodable_entries`] instead.
+	fn current_value() -> Self::Value {
+		sp_io::storage::get(&Self::name().prefix())
+			.and_then(|raw| Value::decode(&mut &raw[..]).ok())
+	}
+
+	fn recorded_values(changes: &[RawChange]) -> Option<(Self::Value, Self::Value)> {
+		let change = change_at(changes, &Self::name().prefix())?;
+
+		Some((decode_value(change.before.as_deref()), decode_value(change.after.as_deref())))
+	}
+
+	fn undecodable_entries(changes: Option<&[RawChange]>) -> Vec<UndecodableEntry> {
+		let key = Self::name().prefix();
+
+		let value = match changes {
+			Some(changes) => change_at(changes, &key).and_then(|change| change.after.clone()),
+			None => sp_io::storage::get(&key).map(|raw| raw.to_vec()),
+		};
+
+		value
+			.filter(|value| Value::decode(&mut &value[..]).is_err())
+			.map(|value| UndecodableEntry { key: OpaqueKey(Vec::new()), value: OpaqueValue(value) })
+			.into_iter()
+			.collect()
+	}
+}
+
+/// Checks a [`StorageDoubleMap`] with it's entries keyed by both keys, instead of nested by the
+/// first key:
+///
+/// ```rust,ignore
+/// do_action::<(Flat<Approvals<T>>, ()), _, _>(|| { ... })
+///     .check_storage::<Flat<Approvals<T>>, _>(map! {
+///         (ALICE, BOB) => MapValueDiff::Added(100),
+///     })
+///     .assert_storage_changes();
+/// ```
+pub struct Flat<S>(PhantomData<S>);
+
+impl<Prefix, Hasher1, Key1, Hasher2, Key2, Value, QueryKind, OnEmpty, MaxValues> CheckStorage
+	for Flat<
+		StorageDoubleMap<
+			Prefix,
+			Hasher1,
+			Key1,
+			Hasher2,
+			Key2,
+			Value,
+			QueryKind,
+			OnEmpty,
+			MaxValues,
+		>,
+	> where
+	Prefix: StorageInstance,
+	Hasher1: StorageHasher + ReversibleStorageHasher,
+	Key1: FullCodec + Debug + Ord,
+	Hasher2: StorageHasher + ReversibleStorageHasher,
+	Key2: FullCodec + Debug + Ord,
+	Value: FullCodec + PartialEq + Diffable,
+	QueryKind: QueryKindTrait<Value, OnEmpty>,
+	OnEmpty: Get<QueryKind::Query> + 'static,
+	MaxValues: Get<Option<u32>>,
+{
+	type Value = BTreeMap<(Key1, Key2), Value>;
+
+	fn name() -> StorageId {
+		StorageId::of::<Prefix>()
+	}
+
+	fn current_value() -> Self::Value {
+		StorageDoubleMap::<
+			Prefix,
+			Hasher1,
+			Key1,
+			Hasher2,
+			Key2,
+			Value,
+			QueryKind,
+			OnEmpty,
+			MaxValues,
+		>::iter()
+		.map(|(k1, k2, v)| ((k1, k2), v))
+		.collect()
+	}
+
+	fn recorded_values(changes: &[RawChange]) -> Option<(Self::Value, Self::Value)> {
+		Some(written_entries(
+			changes,
+			&Self::name().prefix(),
+			decode_double_map_entry::<Hasher1, Key1, Hasher2, Key2, Value>,
+		))
+	}
+
+	fn undecodable_entries(changes: Option<&[RawChange]>) -> Vec<UndecodableEntry> {
+		StorageDoubleMap::<
+			Prefix,
+			Hasher1,
+			Key1,
+			Hasher2,
+			Key2,
+			Value,
+			QueryKind,
+			OnEmpty,
+			MaxValues,
+		>::undecodable_entries(changes)
+	}
+}
+
+/// A raw storage key, without the prefix of it's storage. For maps, this is the concatenation of
+/// the hashed keys.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct OpaqueKey(pub Vec<u8>);
+
+impl Debug for OpaqueKey {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "0x{}", HexDisplay::from(&self.0))
+	}
+}
+
+/// A raw, undecoded storage value.
+#[derive(Clone, PartialEq, Eq)]
+pub struct OpaqueValue(pub Vec<u8>);
+
+impl Debug for OpaqueValue {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "0x{}", HexDisplay::from(&self.0))
+	}
+}
+
+/// Raw values have no structure to diff, so the [`Diffable::ChangeSet`] is the new value.
+impl Diffable for OpaqueValue {
+	type ChangeSet = OpaqueValue;
+
+	fn diff(self, new_value: Self) -> Diff<Self::ChangeSet> {
+		if self == new_value {
+			Diff::NotChanged
+		} else {
+			Diff::ChangedTo(new_value)
+		}
+	}
+}
+
+/// Checks the map `S` by it's raw keys, for maps that use hashers that aren't reversible (i.e.
+/// `Blake2_128` or `Twox128`) and as such can't be iterated by their decoded keys.
+///
+/// The entries are keyed by their [`OpaqueKey`]. Known plaintext keys can be mapped to their
+/// [`OpaqueKey`] with `Opaque::<S>::key` to be used in the expected changes:
+///
+/// ```rust,ignore
+/// do_action::<(Opaque<Accounts<T>>, ()), _, _>(|| { ... })
+///     .check_storage::<Opaque<Accounts<T>>, _>(map! {
+///         Opaque::<Accounts<T>>::key(&alice) => MapValueDiff::Added(100),
+///     })
+///     .assert_storage_changes();
+/// ```
+pub struct Opaque<S>(PhantomData<S>);
+
+/// All of the entries under `prefix`, keyed by their [`OpaqueKey`].
+fn iter_opaque<Value: Decode>(prefix: [u8; 32]) -> BTreeMap<OpaqueKey, Value> {
+	PrefixIterator::<(OpaqueKey, Value)>::new(prefix.to_vec(), prefix.to_vec(), |key, mut value| {
+		Ok((OpaqueKey(key.to_vec()), Value::decode(&mut value)?))
+	})
+	.collect()
+}
+
+/// Strips the 32 byte storage prefix (`twox128(pallet) ++ twox128(storage)`) off of a hashed key.
+fn opaque_key(hashed_key: &[u8]) -> OpaqueKey {
+	OpaqueKey(hashed_key[32..].to_vec())
+}
+
+impl<Prefix, Hasher, Key, Value, QueryKind, OnEmpty, MaxValues> CheckStorage
+	for Opaque<StorageMap<Prefix, Hasher, Key, Value, QueryKind, OnEmpty, MaxValues>>
+where
+	Prefix: StorageInstance,
+	Hasher: StorageHasher,
+	Key: FullCodec,
+	Value: FullCodec + PartialEq + Debug + Diffable,
+	QueryKind: QueryKindTrait<Value, OnEmpty>,
+	OnEmpty: Get<QueryKind::Query> + 'static,
+	MaxValues: Get<Option<u32>>,
+{
+	type Value = BTreeMap<OpaqueKey, Value>;
+
+	fn name() -> StorageId {
+		StorageId::of::<Prefix>()
+	}
+
+	fn current_value() -> Self::Value {
+		iter_opaque(
+			StorageMap::<Prefix, Hasher, Key, Value, QueryKind, OnEmpty, MaxValues>::final_prefix(),
+		)
+	}
+
+	fn recorded_values(changes: &[RawChange]) -> Option<(Self::Value, Self::Value)> {
+		Some(written_entries(changes, &Self::name().prefix(), |key, mut value| {
+			Some((OpaqueKey(key.to_vec()), Value::decode(&mut value).ok()?))
+		}))
+	}
+
+	fn undecodable_entries(changes: Option<&[RawChange]>) -> Vec<UndecodableEntry> {
+		undecodable_under(Self::name().prefix(), changes, |_, mut value| {
+			Value::decode(&mut value).is_ok()
+		})
+	}
+}
+
+impl<Prefix, Hasher, Key, Value, QueryKind, OnEmpty, MaxValues>
+	Opaque<StorageMap<Prefix, Hasher, Key, Value, QueryKind, OnEmpty, MaxValues>>
+where
+	Prefix: StorageInstance,
+	Hasher: StorageHasher,
+	Key: FullCodec,
+	Value: FullCodec,
+	QueryKind: QueryKindTrait<Value, OnEmpty>,
+	OnEmpty: Get<QueryKind::Query> + 'static,
+	MaxValues: Get<Option<u32>>,
+{
+	/// The [`OpaqueKey`] of the entry with the plaintext key `key`.
+	pub fn key<KeyArg: EncodeLike<Key>>(key: KeyArg) -> OpaqueKey {
+		opaque_key(&StorageMap::<Prefix, Hasher, Key, Value, QueryKind, OnEmpty, MaxValues>::hashed_key_for(key))
+	}
+}
+
+/// As for [`CountedStorageMap`], the counter is also checked to change consistently with the number
+/// of entries.
+impl<Prefix, Hasher, Key, Value, QueryKind, OnEmpty, MaxValues> CheckStorage
+	for Opaque<CountedStorageMap<Prefix, Hasher, Key, Value, QueryKind, OnEmpty, MaxValues>>
+where
+	Prefix: CountedStorageMapInstance,
+	Hasher: StorageHasher,
+	Key: FullCodec,
+	Value: FullCodec + PartialEq + Debug + Diffable,
+	QueryKind: QueryKindTrait<Value, OnEmpty>,
+	OnEmpty: Get<QueryKind::Query> + 'static,
+	MaxValues: Get<Option<u32>>,
+{
+	type Value = BTreeMap<OpaqueKey, Value>;
+
+	fn name() -> StorageId {
+		StorageId::of::<Prefix>()
+	}
+
+	fn current_value() -> Self::Value {
+		iter_opaque(Self::name().prefix())
+	}
+
+	fn counter() -> Option<Counter> {
+		let prefix = Self::name().prefix().to_vec();
+
+		Some(
+			Counter {
+				counted: CountedStorageMap::<
+					Prefix,
+					Hasher,
+					Key,
+					Value,
+					QueryKind,
+					OnEmpty,
+					MaxValues,
+				>::count(),
+				entries: PrefixIterator::<()>::new(prefix.clone(), prefix, |_, _| Ok(())).count(),
+			},
+		)
+	}
+
+	fn counter_change(changes: &[RawChange]) -> Option<CounterChange> {
+		Some(counted_map_counter_change::<Prefix>(changes))
+	}
+
+	fn recorded_values(changes: &[RawChange]) -> Option<(Self::Value, Self::Value)> {
+		Some(written_entries(changes, &Self::name().prefix(), |key, mut value| {
+			Some((OpaqueKey(key.to_vec()), Value::decode(&mut value).ok()?))
+		}))
+	}
+
+	fn undecodable_entries(changes: Option<&[RawChange]>) -> Vec<UndecodableEntry> {
+		undecodable_under(Self::name().prefix(), changes, |_, mut value| {
+			Value::decode(&mut value).is_ok()
+		})
+	}
+
+	fn prefixes() -> Vec<Vec<u8>> {
+		vec![
+			Self::name().prefix().to_vec(),
+			StorageId::of::<Prefix::CounterPrefix>().prefix().to_vec(),
+		]
+	}
+}
+
+impl<Prefix, Hasher, Key, Value, QueryKind, OnEmpty, MaxValues>
+	Opaque<CountedStorageMap<Prefix, Hasher, Key, Value, QueryKind, OnEmpty, MaxValues>>
+where
+	Prefix: CountedStorageMapInstance,
+	Hasher: StorageHasher,
+	Key: FullCodec,
+	Value: FullCodec,
+	QueryKind: QueryKindTrait<Value, OnEmpty>,
+	OnEmpty: Get<QueryKind::Query> + 'static,
+	MaxValues: Get<Option<u32>>,
+{
+	/// The [`OpaqueKey`] of the entry with the plaintext key `key`.
+	pub fn key<KeyArg: EncodeLike<Key>>(key: KeyArg) -> OpaqueKey {
+		opaque_key(&CountedStorageMap::<Prefix, Hasher, Key, Value, QueryKind, OnEmpty, MaxValues>::hashed_key_for(key))
+	}
+}
+
+impl<Prefix, Hasher1, Key1, Hasher2, Key2, Value, QueryKind, OnEmpty, MaxValues> CheckStorage
+	for Opaque<
+		StorageDoubleMap<
+			Prefix,
+			Hasher1,
+			Key1,
+			Hasher2,
+			Key2,
+			Value,
+			QueryKind,
+			OnEmpty,
+			MaxValues,
+		>,
+	> where
+	Prefix: StorageInstance,
+	Hasher1: StorageHasher,
+	Key1: FullCodec,
+	Hasher2: StorageHasher,
+	Key2: FullCodec,
+	Value: FullCodec + PartialEq + Debug + Diffable,
+	QueryKind: QueryKindTrait<Value, OnEmpty>,
+	OnEmpty: Get<QueryKind::Query> + 'static,
+	MaxValues: Get<Option<u32>>,
+{
+	type Value = BTreeMap<OpaqueKey, Value>;
+
+	fn name() -> StorageId {
+		StorageId::of::<Prefix>()
+	}
+
+	fn current_value() -> Self::Value {
+		iter_opaque(StorageDoubleMap::<
+			Prefix,
+			Hasher1,
+			Key1,
+			Hasher2,
+			Key2,
+			Value,
+			QueryKind,
+			OnEmpty,
+			MaxValues,
+		>::final_prefix())
+	}
+
+	fn recorded_values(changes: &[RawChange]) -> Option<(Self::Value, Self::Value)> {
+		Some(written_entries(changes, &Self::name().prefix(), |key, mut value| {
+			Some((OpaqueKey(key.to_vec()), Value::decode(&mut value).ok()?))
+		}))
+	}
+
+	fn undecodable_entries(changes: Option<&[RawChange]>) -> Vec<UndecodableEntry> {
+		undecodable_under(Self::name().prefix(), changes, |_, mut value| {
+			Value::decode(&mut value).is_ok()
+		})
+	}
+}
+
+impl<Prefix, Hasher1, Key1, Hasher2, Key2, Value, QueryKind, OnEmpty, MaxValues>
+	Opaque<
+		StorageDoubleMap<
+			Prefix,
+			Hasher1,
+			Key1,
+			Hasher2,
+			Key2,
+			Value,
+			QueryKind,
+			OnEmpty,
+			MaxValues,
+		>,
+	> where
+	Prefix: StorageInstance,
+	Hasher1: StorageHasher,
+	Key1: FullCodec,
+	Hasher2: StorageHasher,
+	Key2: FullCodec,
+	Value: FullCodec,
+	QueryKind: QueryKindTrait<Value, OnEmpty>,
+	OnEmpty: Get<QueryKind::Query> + 'static,
+	MaxValues: Get<Option<u32>>,
+{
+	/// The [`OpaqueKey`] of the entry with the plaintext keys `key1` and `key2`.
+	pub fn key<KeyArg1: EncodeLike<Key1>, KeyArg2: EncodeLike<Key2>>(
+		key1: KeyArg1,
+		key2: KeyArg2,
+	) -> OpaqueKey {
+		opaque_key(&StorageDoubleMap::<
+			Prefix,
+			Hasher1,
+			Key1,
+			Hasher2,
+			Key2,
+			Value,
+			QueryKind,
+			OnEmpty,
+			MaxValues,
+		>::hashed_key_for(key1, key2))
+	}
+}
+
+impl<Prefix, Key, Value, QueryKind, OnEmpty, MaxValues> CheckStorage
+	for Opaque<StorageNMap<Prefix, Key, Value, QueryKind, OnEmpty, MaxValues>>
+where
+	Prefix: StorageInstance,
+	Key: KeyGenerator,
+	Value: FullCodec + PartialEq + Debug + Diffable,
+	QueryKind: QueryKindTrait<Value, OnEmpty>,
+	OnEmpty: Get<QueryKind::Query> + 'static,
+	MaxValues: Get<Option<u32>>,
+{
+	type Value = BTreeMap<OpaqueKey, Value>;
+
+	fn name() -> StorageId {
+		StorageId::of::<Prefix>()
+	}
+
+	fn current_value() -> Self::Value {
+		iter_opaque(StorageNMap::<Prefix, Key, Value, QueryKind, OnEmpty, MaxValues>::final_prefix())
+	}
+
+	fn recorded_values(changes: &[RawChange]) -> Option<(Self::Value, Self::Value)> {
+		Some(written_entries(changes, &Self::name().prefix(), |key, mut value| {
+			Some((OpaqueKey(key.to_vec()), Value::decode(&mut value).ok()?))
+		}))
+	}
+
+	fn undecodable_entries(changes: Option<&[RawChange]>) -> Vec<UndecodableEntry> {
+		undecodable_under(Self::name().prefix(), changes, |_, mut value| {
+			Value::decode(&mut value).is_ok()
+		})
+	}
+}
+
+impl<Prefix, Key, Value, QueryKind, OnEmpty, MaxValues>
+	Opaque<StorageNMap<Prefix, Key, Value, QueryKind, OnEmpty, MaxValues>>
+where
+	Prefix: StorageInstance,
+	Key: KeyGenerator,
+	Value: FullCodec,
+	QueryKind: QueryKindTrait<Value, OnEmpty>,
+	OnEmpty: Get<QueryKind::Query> + 'static,
+	MaxValues: Get<Option<u32>>,
+{
+	/// The [`OpaqueKey`] of the entry with the plaintext key tuple `key`.
+	pub fn key<KeyArg: EncodeLikeTuple<Key::KArg> + TupleToEncodedIter>(key: KeyArg) -> OpaqueKey {
+		opaque_key(
+			&StorageNMap::<Prefix, Key, Value, QueryKind, OnEmpty, MaxValues>::hashed_key_for(key),
+		)
+	}
 }
@@ -0,0 +1,169 @@
+//! Checking the contents of child tries.
+//!
+//! Child tries aren't part of the main trie, so the storages of a pallet don't include them. A
+//! [`ChildTrie`] identifies a child trie by it's [`ChildInfo`], which can then be checked like any
+//! other storage with either [`ChildStorage`], decoding it's keys and values, or
+//! [`RawChildStorage`], by it's raw keys and values:
+//!
+//! ```rust,ignore
+//! struct Contributions;
+//!
+//! impl ChildTrie for Contributions {
+//!     fn name() -> StorageId {
+//!         StorageId { pallet: "Crowdloan", storage: "Contributions" }
+//!     }
+//!
+//!     fn child_info() -> ChildInfo {
+//!         Crowdloan::id_child_info(FUND_INDEX)
+//!     }
+//! }
+//!
+//! type Contributed = ChildStorage<Contributions, AccountId, Balance>;
+//!
+//! do_action::<(Contributed, ()), _, _>(|| { ... })
+//!     .check_storage::<Contributed, _>(map! { ALICE => MapValueDiff::Added(100) })
+//!     .assert_storage_changes();
+//! ```
+
+use core::{fmt::Debug, marker::PhantomData};
+
+use parity_scale_codec::Decode;
+use sp_core::storage::ChildInfo;
+use sp_io::default_child_storage;
+use sp_std::{collections::btree_map::BTreeMap, vec, vec::Vec};
+
+use crate::{
+	check_storage::{
+		written_entries, CheckStorage, OpaqueKey, OpaqueValue, StorageId, UndecodableEntry,
+	},
+	diff::Diffable,
+	recorder::RawChange,
+};
+
+/// Identifies a child trie.
+pub trait ChildTrie {
+	/// The name that the child trie is reported under. This doesn't need to be the name of an
+	/// actual storage.
+	fn name() -> StorageId;
+
+	fn child_info() -> ChildInfo;
+}
+
+/// Checks the entries of the child trie `T`, with their keys decoded as `Key` and their values
+/// decoded as `Value`. Entries that can't be decoded are reported as
+/// [`Undecodable`](crate::error::StorageAssertionErrorKind::Undecodable).
+pub struct ChildStorage<T, Key, Value>(PhantomData<(T, Key, Value)>);
+
+impl<T, Key, Value> CheckStorage for ChildStorage<T, Key, Value>
+where
+	T: ChildTrie,
+	Key: Decode + Ord + Debug,
+	Value: Decode + PartialEq + Debug + Diffable,
+{
+	type Value = BTreeMap<Key, Value>;
+
+	fn name() -> StorageId {
+		T::name()
+	}
+
+	fn current_value() -> Self::Value {
+		raw_entries::<T>()
+			.into_iter()
+			.filter_map(|(key, value)| decode(&key, &value))
+			.collect()
+	}
+
+	fn prefixes() -> Vec<Vec<u8>> {
+		vec![prefix::<T>()]
+	}
+
+	fn recorded_values(changes: &[RawChange]) -> Option<(Self::Value, Self::Value)> {
+		Some(written_entries(changes, &prefix::<T>(), decode))
+	}
+
+	fn undecodable_entries(changes: Option<&[RawChange]>) -> Vec<UndecodableEntry> {
+		let entries = match changes {
+			Some(changes) => {
+				let prefix = prefix::<T>();
+
+				changes
+					.iter()
+					.filter_map(|change| {
+						Some((change.key_under(&prefix)?.to_vec(), change.after.clone()?))
+					})
+					.collect()
+			},
+			None => raw_entries::<T>(),
+		};
+
+		entries
+			.into_iter()
+			.filter(|(key, value)| decode::<Key, Value>(key, value).is_none())
+			.map(|(key, value)| UndecodableEntry { key: OpaqueKey(key), value: OpaqueValue(value) })
+			.collect()
+	}
+}
+
+/// Checks the entries of the child trie `T` by their raw keys and values.
+pub struct RawChildStorage<T>(PhantomData<T>);
+
+impl<T: ChildTrie> CheckStorage for RawChildStorage<T> {
+	type Value = BTreeMap<OpaqueKey, OpaqueValue>;
+
+	fn name() -> StorageId {
+		T::name()
+	}
+
+	fn current_value() -> Self::Value {
+		raw_entries::<T>()
+			.into_iter()
+			.map(|(key, value)| (OpaqueKey(key), OpaqueValue(value)))
+			.collect()
+	}
+
+	fn prefixes() -> Vec<Vec<u8>> {
+		vec![prefix::<T>()]
+	}
+
+	fn recorded_values(changes: &[RawChange]) -> Option<(Self::Value, Self::Value)> {
+		Some(written_entries(changes, &prefix::<T>(), |key, value| {
+			Some((OpaqueKey(key.to_vec()), OpaqueValue(value.to_vec())))
+		}))
+	}
+}
+
+/// The prefix of the keys of the child trie `T` in the [`RawChange`]s, which is also the key of
+/// the root of the child trie in the main trie.
+fn prefix<T: ChildTrie>() -> Vec<u8> {
+	T::child_info().prefixed_storage_key().into_inner()
+}
+
+fn decode<Key: Decode, Value: Decode>(mut key: &[u8], mut value: &[u8]) -> Option<(Key, Value)> {
+	Some((Key::decode(&mut key).ok()?, Value::decode(&mut value).ok()?))
+}
+
+/// All of the raw entries of the child trie `T`.
+fn raw_entries<T: ChildTrie>() -> Vec<(Vec<u8>, Vec<u8>)> {
+	let child_info = T::child_info();
+	let storage_key = child_info.storage_key();
+
+	let mut entries = Vec::new();
+
+	// `next_key` only returns keys after the provided key, so the empty key has to be read
+	// separately
+	if let Some(value) = default_child_storage::get(storage_key, &[]) {
+		entries.push((Vec::new(), value));
+	}
+
+	let mut previous_key = Vec::new();
+
+	while let Some(key) = default_child_storage::next_key(storage_key, &previous_key) {
+		if let Some(value) = default_child_storage::get(storage_key, &key) {
+			entries.push((key.clone(), value));
+		}
+
+		previous_key = key;
+	}
+
+	entries
+}
@@ -1,10 +1,66 @@
-use core::{convert::Infallible, fmt::Debug};
+use core::{
+	convert::Infallible,
+	fmt::Debug,
+	iter::{self, Peekable},
+};
 
-use frame_support::{traits::Get, BoundedBTreeMap};
-use sp_arithmetic::{fixed_point::FixedU64, FixedU128};
-use sp_core::sr25519;
-use sp_runtime::Perbill;
-use sp_std::collections::btree_map::BTreeMap;
+/// Derive [`Diffable`] for a struct or enum.
+///
+/// For structs, a companion `<Name>ChangeSet` struct is generated alongside the type, with
+/// every field `field: T` becoming `field: Diff<T::ChangeSet>`. This allows failed assertions
+/// to point at the exact field that changed, rather than the entire value:
+///
+/// ```rust,ignore
+/// #[derive(Debug, Diffable)]
+/// pub struct Pool {
+///     pub owner: AccountId32,
+///     pub fee: Perbill,
+/// }
+///
+/// // generates:
+/// pub struct PoolChangeSet {
+///     pub owner: Diff<<AccountId32 as Diffable>::ChangeSet>,
+///     pub fee: Diff<<Perbill as Diffable>::ChangeSet>,
+/// }
+/// ```
+///
+/// For enums, the generated `<Name>ChangeSet` distinguishes switching to a different variant
+/// from changes to the fields of the same variant, similar to how [`OptionDiff`] distinguishes
+/// [`OptionDiff::WasNoneNowSome`] from [`OptionDiff::ValueChanged`]:
+///
+/// ```rust,ignore
+/// #[derive(Debug, PartialEq, Diffable)]
+/// pub enum Status {
+///     Pending { since: BlockNumber },
+///     Active { stake: Balance },
+///     Closed,
+/// }
+///
+/// // generates:
+/// pub enum StatusChangeSet {
+///     /// The value was switched to a different variant.
+///     SwitchedTo(Status),
+///     Pending { since: Diff<<BlockNumber as Diffable>::ChangeSet> },
+///     Active { stake: Diff<<Balance as Diffable>::ChangeSet> },
+/// }
+/// ```
+///
+/// Variants without any fields can't change without switching to another variant, and as such
+/// have no corresponding variant in the `ChangeSet`.
+pub use change_set_macro::Diffable;
+use frame_support::{
+	traits::Get, weights::Weight, BoundedBTreeMap, BoundedBTreeSet, BoundedVec, WeakBoundedVec,
+};
+use sp_arithmetic::{
+	fixed_point::{FixedI128, FixedI64, FixedU64},
+	FixedPointNumber, FixedU128, PerU16, Percent, Permill, Perquintill,
+};
+use sp_core::{ecdsa, ed25519, sr25519, H160, H256, H512, U256};
+use sp_runtime::{AccountId32, MultiAddress, Perbill};
+use sp_std::{
+	collections::{btree_map::BTreeMap, btree_set::BTreeSet},
+	vec::Vec,
+};
 
 // TODO: docs lol
 pub trait Diffable: Debug {
@@ -145,6 +201,154 @@ mod test_btree_map_diff {
 	}
 }
 
+#[cfg(test)]
+mod test_derive_diffable {
+	use super::{Diff, Diffable};
+
+	#[derive(Debug, Diffable)]
+	struct Named {
+		a: u32,
+		b: Option<u64>,
+	}
+
+	#[derive(Debug, Diffable)]
+	struct Unnamed(u8, u16);
+
+	#[derive(Debug, Diffable)]
+	struct Unit;
+
+	#[derive(Debug, PartialEq, Diffable)]
+	enum Status {
+		Pending { since: u32 },
+		Active(u64, u8),
+		Closed,
+	}
+
+	#[test]
+	fn test_not_changed() {
+		assert_eq!(Named { a: 1, b: Some(2) }.diff(Named { a: 1, b: Some(2) }), Diff::NotChanged);
+		assert_eq!(Unnamed(1, 2).diff(Unnamed(1, 2)), Diff::NotChanged);
+		assert_eq!(Unit.diff(Unit), Diff::NotChanged);
+	}
+
+	#[test]
+	fn test_field_changed() {
+		assert_eq!(
+			Named { a: 1, b: Some(2) }.diff(Named { a: 3, b: Some(2) }),
+			Diff::ChangedTo(NamedChangeSet { a: Diff::ChangedTo(3), b: Diff::NotChanged })
+		);
+		assert_eq!(
+			Unnamed(1, 2).diff(Unnamed(1, 4)),
+			Diff::ChangedTo(UnnamedChangeSet(Diff::NotChanged, Diff::ChangedTo(4)))
+		);
+	}
+
+	#[test]
+	fn test_enum_not_changed() {
+		assert_eq!(
+			Status::Pending { since: 1 }.diff(Status::Pending { since: 1 }),
+			Diff::NotChanged
+		);
+		assert_eq!(Status::Active(1, 2).diff(Status::Active(1, 2)), Diff::NotChanged);
+		assert_eq!(Status::Closed.diff(Status::Closed), Diff::NotChanged);
+	}
+
+	#[test]
+	fn test_enum_same_variant_changed() {
+		assert_eq!(
+			Status::Pending { since: 1 }.diff(Status::Pending { since: 2 }),
+			Diff::ChangedTo(StatusChangeSet::Pending { since: Diff::ChangedTo(2) })
+		);
+		assert_eq!(
+			Status::Active(1, 2).diff(Status::Active(1, 3)),
+			Diff::ChangedTo(StatusChangeSet::Active(Diff::NotChanged, Diff::ChangedTo(3)))
+		);
+	}
+
+	#[test]
+	fn test_enum_switched_variant() {
+		assert_eq!(
+			Status::Pending { since: 1 }.diff(Status::Active(1, 2)),
+			Diff::ChangedTo(StatusChangeSet::SwitchedTo(Status::Active(1, 2)))
+		);
+		assert_eq!(
+			Status::Active(1, 2).diff(Status::Closed),
+			Diff::ChangedTo(StatusChangeSet::SwitchedTo(Status::Closed))
+		);
+	}
+}
+
+/// Describes the diff between two sets.
+#[derive(Debug, PartialEq, Eq)]
+pub struct SetDiff<T: Ord> {
+	/// The values that were not in the original set, but are in the updated set.
+	pub added: BTreeSet<T>,
+	/// The values that were in the original set, but are not in the updated set.
+	pub removed: BTreeSet<T>,
+}
+
+// deriving Default adds a bound to T, so manually impl it for now
+impl<T: Ord> Default for SetDiff<T> {
+	fn default() -> Self {
+		Self { added: BTreeSet::new(), removed: BTreeSet::new() }
+	}
+}
+
+impl<T: Ord + Debug, S: Get<u32>> Diffable for BoundedBTreeSet<T, S> {
+	type ChangeSet = <BTreeSet<T> as Diffable>::ChangeSet;
+
+	fn diff(self, updated: Self) -> Diff<Self::ChangeSet> {
+		self.into_inner().diff(updated.into_inner())
+	}
+}
+
+impl<T: Ord + Debug> Diffable for BTreeSet<T> {
+	type ChangeSet = SetDiff<T>;
+
+	fn diff(self, mut updated: Self) -> Diff<Self::ChangeSet> {
+		// anything left in `updated` after removing the values of the original set was added
+		let removed =
+			self.into_iter().filter(|value| !updated.remove(value)).collect::<BTreeSet<_>>();
+
+		if removed.is_empty() && updated.is_empty() {
+			Diff::NotChanged
+		} else {
+			Diff::ChangedTo(SetDiff { added: updated, removed })
+		}
+	}
+}
+
+#[cfg(test)]
+mod test_btree_set_diff {
+	use std::collections::BTreeSet;
+
+	use super::{Diff, Diffable, SetDiff};
+
+	#[test]
+	fn test_not_changed() {
+		let set = [1, 2, 3].into_iter().collect::<BTreeSet<u32>>();
+
+		let updated = [1, 2, 3].into_iter().collect::<BTreeSet<u32>>();
+
+		assert_eq!(set.diff(updated), Diff::NotChanged);
+	}
+
+	#[test]
+	fn test_added_and_removed() {
+		let set = [1, 2, 3].into_iter().collect::<BTreeSet<u32>>();
+
+		let updated = [2, 3, 4].into_iter().collect::<BTreeSet<u32>>();
+
+		assert_eq!(
+			set.diff(updated),
+			Diff::ChangedTo(SetDiff {
+				added: [4].into_iter().collect(),
+				removed: [1].into_iter().collect()
+			})
+		);
+	}
+}
+
 impl<T: Diffable + PartialEq + Eq + Debug> Diffable for Option<T> {
 	type ChangeSet = OptionDiff<T>;
 
@@ -175,6 +379,281 @@ pub enum OptionDiff<T: Diffable> {
 	WasSomeNowNone,
 }
 
+/// Describes a single change between two sequences.
+///
+/// The [`Diffable::ChangeSet`] of a sequence is a list of these changes, computed from the longest
+/// common subsequence of the original and updated sequence. Elements that were removed and inserted
+/// at the same position are reported as [`VecDiff::Changed`], and elements that were appended to
+/// the end of the original sequence are reported as [`VecDiff::Pushed`]:
+///
+/// ```rust,ignore
+/// let queue = vec![1, 2, 3];
+/// let updated = vec![2, 3, 4];
+/// assert_eq!(
+///     queue.diff(updated),
+///     Diff::ChangedTo(vec![VecDiff::Removed { index: 0 }, VecDiff::Pushed(4)])
+/// );
+/// ```
+#[derive(Debug, PartialEq, Eq)]
+pub enum VecDiff<T: Diffable> {
+	/// The value was appended after the last element of the original sequence.
+	Pushed(T),
+	/// The value was inserted at `index`, where `index` is the position of the value in the
+	/// updated sequence.
+	Inserted { index: usize, value: T },
+	/// The element at `index` in the original sequence was removed.
+	Removed { index: usize },
+	/// The element at `index` in the original sequence was changed. Contained is the diff between
+	/// the original and updated element.
+	Changed { index: usize, change: T::ChangeSet },
+}
+
+impl<T: Diffable + PartialEq, S: Get<u32>> Diffable for BoundedVec<T, S> {
+	type ChangeSet = <Vec<T> as Diffable>::ChangeSet;
+
+	fn diff(self, updated: Self) -> Diff<Self::ChangeSet> {
+		self.into_inner().diff(updated.into_inner())
+	}
+}
+
+impl<T: Diffable + PartialEq, S: Get<u32>> Diffable for WeakBoundedVec<T, S> {
+	type ChangeSet = <Vec<T> as Diffable>::ChangeSet;
+
+	fn diff(self, updated: Self) -> Diff<Self::ChangeSet> {
+		self.into_inner().diff(updated.into_inner())
+	}
+}
+
+impl<T: Diffable + PartialEq> Diffable for Vec<T> {
+	type ChangeSet = Vec<VecDiff<T>>;
+
+	fn diff(self, updated: Self) -> Diff<Self::ChangeSet> {
+		let (original_len, updated_len) = (self.len(), updated.len());
+
+		// elements shared at the start and end of both sequences are unchanged, no need to include
+		// them in the (quadratic) lcs table
+		let prefix_len = self.iter().zip(&updated).take_while(|(a, b)| a == b).count();
+		let suffix_len = self[prefix_len..]
+			.iter()
+			.rev()
+			.zip(updated[prefix_len..].iter().rev())
+			.take_while(|(a, b)| a == b)
+			.count();
+
+		if prefix_len + suffix_len == original_len && original_len == updated_len {
+			return Diff::NotChanged;
+		}
+
+		let edits = lcs_edits(
+			&self[prefix_len..original_len - suffix_len],
+			&updated[prefix_len..updated_len - suffix_len],
+		);
+
+		let mut original = self.into_iter().map(Some).collect::<Vec<_>>();
+		let mut updated = updated.into_iter().map(Some).collect::<Vec<_>>();
+
+		let mut change_set = Vec::new();
+
+		// (original index, updated index), offset by the common prefix
+		let (mut i, mut j) = (prefix_len, prefix_len);
+		let mut edits = edits.into_iter().peekable();
+
+		while let Some(edit) = edits.next() {
+			if edit == Edit::Keep {
+				i += 1;
+				j += 1;
+				continue;
+			}
+
+			// collect the run of removals and insertions between two unchanged elements
+			let (mut removed, mut inserted) = (0, 0);
+			for edit in iter_run(edit, &mut edits) {
+				match edit {
+					Edit::Remove => removed += 1,
+					Edit::Insert => inserted += 1,
+					Edit::Keep => unreachable!("runs do not contain unchanged elements; qed;"),
+				}
+			}
+
+			let changed = removed.min(inserted);
+
+			for k in 0..changed {
+				let (old, new) = (take(&mut original, i + k), take(&mut updated, j + k));
+
+				if let Diff::ChangedTo(change) = old.diff(new) {
+					change_set.push(VecDiff::Changed { index: i + k, change });
+				}
+			}
+
+			for k in changed..removed {
+				change_set.push(VecDiff::Removed { index: i + k });
+			}
+
+			// if there are no more elements of the original sequence after this run, the inserted
+			// elements were appended to the end of it
+			let is_tail = i + removed == original_len;
+
+			for k in changed..inserted {
+				let value = take(&mut updated, j + k);
+
+				change_set.push(if is_tail {
+					VecDiff::Pushed(value)
+				} else {
+					VecDiff::Inserted { index: j + k, value }
+				});
+			}
+
+			i += removed;
+			j += inserted;
+		}
+
+		if change_set.is_empty() {
+			Diff::NotChanged
+		} else {
+			Diff::ChangedTo(change_set)
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Edit {
+	Keep,
+	Remove,
+	Insert,
+}
+
+/// The largest lcs table (in entries) that [`lcs_edits`] builds, about 16 MiB.
+const MAX_LCS_TABLE_LEN: usize = 1 << 22;
+
+/// The shortest edit script between `original` and `updated`, based on their longest common
+/// subsequence.
+///
+/// The lcs table is quadratic in the length of the sequences, so if it would have more than
+/// [`MAX_LCS_TABLE_LEN`] entries, all of `original` is removed and all of `updated` is inserted
+/// instead. The run of removals and insertions is then diffed element by element, by index.
+pub(crate) fn lcs_edits<T: PartialEq>(original: &[T], updated: &[T]) -> Vec<Edit> {
+	let (n, m) = (original.len(), updated.len());
+
+	if (n + 1).saturating_mul(m + 1) > MAX_LCS_TABLE_LEN {
+		return (0..n).map(|_| Edit::Remove).chain((0..m).map(|_| Edit::Insert)).collect();
+	}
+
+	// lengths[i][j] is the length of the lcs of original[i..] and updated[j..]
+	let mut lengths = vec![vec![0_u32; m + 1]; n + 1];
+
+	for i in (0..n).rev() {
+		for j in (0..m).rev() {
+			lengths[i][j] = if original[i] == updated[j] {
+				lengths[i + 1][j + 1] + 1
+			} else {
+				lengths[i + 1][j].max(lengths[i][j + 1])
+			};
+		}
+	}
+
+	let mut edits = Vec::with_capacity(n.max(m));
+	let (mut i, mut j) = (0, 0);
+
+	while i < n && j < m {
+		if original[i] == updated[j] {
+			edits.push(Edit::Keep);
+			i += 1;
+			j += 1;
+		} else if lengths[i + 1][j] >= lengths[i][j + 1] {
+			edits.push(Edit::Remove);
+			i += 1;
+		} else {
+			edits.push(Edit::Insert);
+			j += 1;
+		}
+	}
+
+	edits.extend((i..n).map(|_| Edit::Remove));
+	edits.extend((j..m).map(|_| Edit::Insert));
+
+	edits
+}
+
+/// The run of non-[`Edit::Keep`] edits starting with `first`.
+fn iter_run<I: Iterator<Item = Edit>>(
+	first: Edit,
+	edits: &mut Peekable<I>,
+) -> impl Iterator<Item = Edit> + '_ {
+	iter::once(first).chain(iter::from_fn(move || edits.next_if(|edit| *edit != Edit::Keep)))
+}
+
+fn take<T>(values: &mut [Option<T>], index: usize) -> T {
+	values[index].take().expect("each element is only taken once; qed;")
+}
+
+#[cfg(test)]
+mod test_vec_diff {
+	use super::{lcs_edits, Diff, Diffable, Edit, VecDiff, MAX_LCS_TABLE_LEN};
+
+	#[test]
+	fn test_not_changed() {
+		assert_eq!(vec![1_u32, 2, 3].diff(vec![1, 2, 3]), Diff::NotChanged);
+		assert_eq!(Vec::<u32>::new().diff(vec![]), Diff::NotChanged);
+	}
+
+	#[test]
+	fn test_pushed() {
+		assert_eq!(
+			vec![1_u32, 2, 3].diff(vec![1, 2, 3, 4, 5]),
+			Diff::ChangedTo(vec![VecDiff::Pushed(4), VecDiff::Pushed(5)])
+		);
+		assert_eq!(Vec::new().diff(vec![1_u32]), Diff::ChangedTo(vec![VecDiff::Pushed(1)]));
+	}
+
+	#[test]
+	fn test_queue() {
+		assert_eq!(
+			vec![1_u32, 2, 3].diff(vec![2, 3, 4]),
+			Diff::ChangedTo(vec![VecDiff::Removed { index: 0 }, VecDiff::Pushed(4)])
+		);
+	}
+
+	#[test]
+	fn test_inserted_and_removed() {
+		assert_eq!(
+			vec![1_u32, 2, 3].diff(vec![1, 4, 2]),
+			Diff::ChangedTo(vec![
+				VecDiff::Inserted { index: 1, value: 4 },
+				VecDiff::Removed { index: 2 }
+			])
+		);
+	}
+
+	#[test]
+	fn test_changed() {
+		assert_eq!(
+			vec![1_u32, 2, 3].diff(vec![1, 5, 3, 6]),
+			Diff::ChangedTo(vec![VecDiff::Changed { index: 1, change: 5 }, VecDiff::Pushed(6)])
+		);
+	}
+
+	#[test]
+	fn test_long_sequences_are_diffed_by_index() {
+		let len = 4096_u32;
+		assert!((len as usize + 1).pow(2) > MAX_LCS_TABLE_LEN);
+
+		let original = (0..len).collect::<Vec<_>>();
+		let updated = (1..=len).collect::<Vec<_>>();
+
+		let edits = lcs_edits(&original, &updated);
+		assert_eq!(edits.len(), 2 * len as usize);
+		assert!(edits[..len as usize].iter().all(|edit| *edit == Edit::Remove));
+		assert!(edits[len as usize..].iter().all(|edit| *edit == Edit::Insert));
+
+		// every element is changed in place, instead of the first being removed and one pushed
+		let Diff::ChangedTo(change_set) = original.diff(updated) else {
+			panic!("the sequence was changed");
+		};
+		assert_eq!(change_set.len(), len as usize);
+		assert!(change_set.iter().all(|change| matches!(change, VecDiff::Changed { .. })));
+	}
+}
+
 impl Diffable for () {
 	type ChangeSet = Infallible;
 
@@ -183,6 +662,90 @@ impl Diffable for () {
 	}
 }
 
+macro_rules! impl_diff_tuples {
+	($($T:ident $idx:tt),+) => {
+		impl<$($T: Diffable),+> Diffable for ($($T,)+) {
+			type ChangeSet = ($(Diff<$T::ChangeSet>,)+);
+
+			fn diff(self, new_value: Self) -> Diff<Self::ChangeSet> {
+				let change_set = ($(self.$idx.diff(new_value.$idx),)+);
+
+				if $(matches!(change_set.$idx, Diff::NotChanged))&&+ {
+					Diff::NotChanged
+				} else {
+					Diff::ChangedTo(change_set)
+				}
+			}
+		}
+	};
+}
+
+impl_diff_tuples!(A 0);
+impl_diff_tuples!(A 0, B 1);
+impl_diff_tuples!(A 0, B 1, C 2);
+impl_diff_tuples!(A 0, B 1, C 2, D 3);
+impl_diff_tuples!(A 0, B 1, C 2, D 3, E 4);
+impl_diff_tuples!(A 0, B 1, C 2, D 3, E 4, F 5);
+impl_diff_tuples!(A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+impl_diff_tuples!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
+impl_diff_tuples!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8);
+impl_diff_tuples!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9);
+impl_diff_tuples!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10);
+impl_diff_tuples!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10, L 11);
+
+/// The [`Diffable::ChangeSet`] of an array is a map of the positions that changed to the diff of
+/// the element at that position.
+impl<T: Diffable, const N: usize> Diffable for [T; N] {
+	type ChangeSet = BTreeMap<usize, T::ChangeSet>;
+
+	fn diff(self, new_value: Self) -> Diff<Self::ChangeSet> {
+		let map = self
+			.into_iter()
+			.zip(new_value)
+			.enumerate()
+			.filter_map(|(index, (old, new))| match old.diff(new) {
+				Diff::NotChanged => None,
+				Diff::ChangedTo(changed) => Some((index, changed)),
+			})
+			.collect::<Self::ChangeSet>();
+
+		if map.is_empty() {
+			Diff::NotChanged
+		} else {
+			Diff::ChangedTo(map)
+		}
+	}
+}
+
+#[cfg(test)]
+mod test_tuple_and_array_diff {
+	use std::collections::BTreeMap;
+
+	use super::{Diff, Diffable};
+
+	#[test]
+	fn test_tuple() {
+		assert_eq!((1_u128, 2_u32).diff((1, 2)), Diff::NotChanged);
+		assert_eq!(
+			(1_u128, 2_u32).diff((1, 3)),
+			Diff::ChangedTo((Diff::NotChanged, Diff::ChangedTo(3)))
+		);
+	}
+
+	#[test]
+	fn test_array() {
+		assert_eq!([1_u8; 32].diff([1; 32]), Diff::NotChanged);
+
+		let mut updated = [1_u8; 32];
+		updated[3] = 7;
+
+		assert_eq!(
+			[1_u8; 32].diff(updated),
+			Diff::ChangedTo([(3, 7)].into_iter().collect::<BTreeMap<_, _>>())
+		);
+	}
+}
+
 macro_rules! impl_diff_primitives {
 	($ty: ty) => {
 		impl Diffable for $ty {
@@ -213,8 +776,209 @@ impl_diff_primitives!(i32);
 impl_diff_primitives!(i64);
 impl_diff_primitives!(i128);
 
-// other types that work with this macro
+impl_diff_primitives!(bool);
+
+// per things
+impl_diff_primitives!(Percent);
+impl_diff_primitives!(PerU16);
+impl_diff_primitives!(Permill);
 impl_diff_primitives!(Perbill);
+impl_diff_primitives!(Perquintill);
+
+// fixed point
 impl_diff_primitives!(FixedU128);
 impl_diff_primitives!(FixedU64);
+impl_diff_primitives!(FixedI128);
+impl_diff_primitives!(FixedI64);
+
+// hashes and big integers
+impl_diff_primitives!(H160);
+impl_diff_primitives!(H256);
+impl_diff_primitives!(H512);
+impl_diff_primitives!(U256);
+
+// accounts and keys
+impl_diff_primitives!(AccountId32);
 impl_diff_primitives!(sr25519::Public);
+impl_diff_primitives!(ed25519::Public);
+impl_diff_primitives!(ecdsa::Public);
+
+// other types that work with this macro
+impl_diff_primitives!(Weight);
+
+impl<AccountId, AccountIndex> Diffable for MultiAddress<AccountId, AccountIndex>
+where
+	AccountId: PartialEq + Debug,
+	AccountIndex: PartialEq + Debug,
+{
+	type ChangeSet = Self;
+
+	fn diff(self, new_value: Self) -> Diff<Self::ChangeSet> {
+		if self == new_value {
+			Diff::NotChanged
+		} else {
+			Diff::ChangedTo(new_value)
+		}
+	}
+}
+
+/// Describes the difference between two numeric values.
+///
+/// This is useful for values such as balances and counters, where the amount the value changed by
+/// is more interesting than the new value (which may depend on genesis, fees, etc).
+#[derive(Debug, PartialEq, Eq)]
+pub enum Delta<T> {
+	/// The value was increased by the contained amount.
+	Increased(T),
+	/// The value was decreased by the contained amount.
+	Decreased(T),
+}
+
+/// Wrapper around a numeric value, diffing it by it's [`Delta`] instead of by it's new value.
+///
+/// This is opt-in; see [`IntoDelta`] and [`AsDelta`](crate::check_storage::AsDelta) for how to
+/// use this with storages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Numeric<T>(pub T);
+
+/// Converts a value into one where all the contained numeric values are wrapped in [`Numeric`],
+/// and as such diffed by their [`Delta`].
+pub trait IntoDelta {
+	type Output: Diffable;
+
+	fn into_delta(self) -> Self::Output;
+}
+
+impl<T> IntoDelta for Option<T>
+where
+	T: IntoDelta,
+	T::Output: PartialEq + Eq,
+{
+	type Output = Option<T::Output>;
+
+	fn into_delta(self) -> Self::Output {
+		self.map(IntoDelta::into_delta)
+	}
+}
+
+impl<K, V> IntoDelta for BTreeMap<K, V>
+where
+	K: Ord + Debug,
+	V: IntoDelta,
+	V::Output: PartialEq,
+{
+	type Output = BTreeMap<K, V::Output>;
+
+	fn into_delta(self) -> Self::Output {
+		self.into_iter().map(|(k, v)| (k, v.into_delta())).collect()
+	}
+}
+
+/// Implements diffing by [`Delta`] for a numeric type `$ty`, where `$magnitude` is able to hold the
+/// difference between any two values of `$ty` (i.e. the unsigned counterpart of a signed type).
+/// Clamping the difference to `$ty` would misreport changes spanning more than half of it's range,
+/// such as from `i8::MIN` to `i8::MAX`.
+macro_rules! impl_diff_delta {
+	($ty: ty => $magnitude: ty, |$old: ident, $new: ident| $abs_diff: expr) => {
+		impl Diffable for Numeric<$ty> {
+			type ChangeSet = Delta<$magnitude>;
+
+			fn diff(self, new_value: Self) -> Diff<Self::ChangeSet> {
+				let ($old, $new) = (self.0, new_value.0);
+				let change: $magnitude = $abs_diff;
+
+				if $new > $old {
+					Diff::ChangedTo(Delta::Increased(change))
+				} else if $new < $old {
+					Diff::ChangedTo(Delta::Decreased(change))
+				} else {
+					Diff::NotChanged
+				}
+			}
+		}
+
+		impl IntoDelta for $ty {
+			type Output = Numeric<$ty>;
+
+			fn into_delta(self) -> Self::Output {
+				Numeric(self)
+			}
+		}
+	};
+	($($ty: ty => $magnitude: ty),* $(,)?) => {
+		$(impl_diff_delta!($ty => $magnitude, |old, new| old.abs_diff(new));)*
+	};
+}
+
+// unsigned
+impl_diff_delta!(u8 => u8, u16 => u16, u32 => u32, u64 => u64, u128 => u128);
+
+// signed
+impl_diff_delta!(i8 => u8, i16 => u16, i32 => u32, i64 => u64, i128 => u128);
+
+// fixed point, where the signed and unsigned types of the same width share the same accuracy
+impl_diff_delta!(FixedU128 => FixedU128, |old, new| FixedU128::from_inner(
+	old.into_inner().abs_diff(new.into_inner())
+));
+impl_diff_delta!(FixedU64 => FixedU64, |old, new| FixedU64::from_inner(
+	old.into_inner().abs_diff(new.into_inner())
+));
+impl_diff_delta!(FixedI128 => FixedU128, |old, new| FixedU128::from_inner(
+	old.into_inner().abs_diff(new.into_inner())
+));
+impl_diff_delta!(FixedI64 => FixedU64, |old, new| FixedU64::from_inner(
+	old.into_inner().abs_diff(new.into_inner())
+));
+
+#[cfg(test)]
+mod test_delta_diff {
+	use std::collections::BTreeMap;
+
+	use sp_arithmetic::{
+		fixed_point::{FixedI128, FixedI64, FixedU64},
+		FixedPointNumber, FixedU128,
+	};
+
+	use super::{Delta, Diff, Diffable, IntoDelta, MapValueDiff, Numeric};
+
+	#[test]
+	fn test_delta() {
+		assert_eq!(Numeric(10_u128).diff(Numeric(10)), Diff::NotChanged);
+		assert_eq!(Numeric(10_u128).diff(Numeric(110)), Diff::ChangedTo(Delta::Increased(100)));
+		assert_eq!(Numeric(10_i8).diff(Numeric(-10)), Diff::ChangedTo(Delta::Decreased(20)));
+	}
+
+	#[test]
+	fn test_signed_delta_spanning_range() {
+		assert_eq!(Numeric(i8::MIN).diff(Numeric(i8::MAX)), Diff::ChangedTo(Delta::Increased(255)));
+		assert_eq!(
+			Numeric(i128::MAX).diff(Numeric(i128::MIN)),
+			Diff::ChangedTo(Delta::Decreased(u128::MAX))
+		);
+		assert_eq!(
+			Numeric(FixedI64::from_inner(i64::MIN)).diff(Numeric(FixedI64::from_inner(i64::MAX))),
+			Diff::ChangedTo(Delta::Increased(FixedU64::from_inner(u64::MAX)))
+		);
+		assert_eq!(
+			Numeric(FixedI128::saturating_from_rational(-3, 2))
+				.diff(Numeric(FixedI128::saturating_from_integer(1))),
+			Diff::ChangedTo(Delta::Increased(FixedU128::saturating_from_rational(5, 2)))
+		);
+	}
+
+	#[test]
+	fn test_into_delta() {
+		let map = [(1, 10), (2, 20)].into_iter().collect::<BTreeMap<u32, u64>>();
+
+		let updated = [(1, 15), (2, 20)].into_iter().collect::<BTreeMap<u32, u64>>();
+
+		assert_eq!(
+			map.into_delta().diff(updated.into_delta()),
+			Diff::ChangedTo(
+				[(1, MapValueDiff::Changed(Delta::Increased(5)))]
+					.into_iter()
+					.collect::<BTreeMap<u32, _>>()
+			)
+		);
+	}
+}
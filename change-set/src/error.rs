@@ -0,0 +1,185 @@
+use core::fmt::{self, Debug, Display};
+
+use sp_core::hexdisplay::HexDisplay;
+use sp_runtime::DispatchError;
+use sp_std::{boxed::Box, vec::Vec};
+
+use crate::{check_storage::StorageId, render::Renderer};
+
+/// A failed assertion on the changes of a single storage.
+///
+/// `expected` and `found` are type-erased, as the [`Diffable::ChangeSet`]s of the checked storages
+/// are all of different types.
+///
+/// [`Diffable::ChangeSet`]: crate::diff::Diffable::ChangeSet
+pub struct StorageAssertionError {
+	/// The storage that the assertion failed for.
+	pub storage: StorageId,
+	pub kind: StorageAssertionErrorKind,
+	/// The expected change, if any.
+	pub expected: Option<Box<dyn Debug + Send + Sync>>,
+	/// The found change, if any.
+	pub found: Option<Box<dyn Debug + Send + Sync>>,
+}
+
+/// The way in which the found changes of a storage differed from the expected changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum StorageAssertionErrorKind {
+	/// The storage was expected to be unchanged, but it was changed.
+	UnexpectedChange,
+	/// The storage was expected to be changed, but it was unchanged.
+	MissingChange,
+	/// The storage was changed, but not as expected.
+	Mismatch,
+	/// The storage's counter didn't change by as much as the number of entries in the storage,
+	/// i.e. the counter of a `CountedStorageMap` wasn't updated consistently with the inserted and
+	/// removed entries. `expected` is the change of the number of entries, and `found` is the
+	/// change of the counter.
+	CounterDrift,
+	/// An entry of the storage couldn't be decoded, and was skipped when reading the storage.
+	/// `found` is the raw [`UndecodableEntry`](crate::check_storage::UndecodableEntry).
+	Undecodable,
+}
+
+impl Debug for StorageAssertionError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("StorageAssertionError")
+			.field("storage", &self.storage)
+			.field("kind", &self.kind)
+			.field("expected", &self.expected)
+			.field("found", &self.found)
+			.finish()
+	}
+}
+
+impl Display for StorageAssertionError {
+	/// Renders the error with [`Renderer::from_env`].
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(&Renderer::from_env().render(self))
+	}
+}
+
+impl std::error::Error for StorageAssertionError {}
+
+/// A write to a key that isn't part of any of the declared storages, found in
+/// [strict mode](crate::AssertableDiffableStorageAction::strict).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UndeclaredWrite {
+	/// The raw key that was written to.
+	pub key: Vec<u8>,
+	/// The pallet that the key belongs to, if it's the pallet of any of the declared storages or
+	/// one of the pallets passed to
+	/// [`known_pallets`](crate::AssertableDiffableStorageAction::known_pallets).
+	pub pallet: Option<&'static str>,
+	/// The raw value before the action, if any.
+	pub before: Option<Vec<u8>>,
+	/// The raw value after the action, if any.
+	pub after: Option<Vec<u8>>,
+}
+
+impl Display for UndeclaredWrite {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "ERROR undeclared write to key 0x{}", HexDisplay::from(&self.key))?;
+
+		if let Some(pallet) = self.pallet {
+			write!(f, " of pallet {pallet}")?;
+		}
+
+		let fmt_value = |f: &mut fmt::Formatter<'_>, value: &Option<Vec<u8>>| match value {
+			Some(value) => write!(f, "0x{}", HexDisplay::from(value)),
+			None => f.write_str("<none>"),
+		};
+
+		f.write_str(": ")?;
+		fmt_value(f, &self.before)?;
+		f.write_str(" -> ")?;
+		fmt_value(f, &self.after)
+	}
+}
+
+impl std::error::Error for UndeclaredWrite {}
+
+/// The action didn't have the expected result, as set by
+/// [`expect_ok`](crate::AssertableDiffableStorageAction::expect_ok) or
+/// [`expect_err`](crate::AssertableDiffableStorageAction::expect_err).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnexpectedOutcome {
+	/// The error that the action was expected to fail with, or `None` if it was expected to
+	/// succeed.
+	pub expected: Option<DispatchError>,
+	/// The error that the action failed with, or `None` if it succeeded.
+	pub found: Option<DispatchError>,
+}
+
+impl Display for UnexpectedOutcome {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self.expected {
+			Some(expected) => write!(f, "ERROR expected the action to fail with {expected:?}")?,
+			None => f.write_str("ERROR expected the action to succeed")?,
+		}
+
+		match self.found {
+			Some(found) => write!(f, ", found {found:?}"),
+			None => f.write_str(", found success"),
+		}
+	}
+}
+
+impl std::error::Error for UnexpectedOutcome {}
+
+/// All of the failed assertions of a single
+/// [`AssertableDiffableStorageAction`](crate::AssertableDiffableStorageAction).
+///
+/// This is returned by
+/// [`try_assert_storage_changes`](crate::AssertableDiffableStorageAction::try_assert_storage_changes),
+/// and can be inspected, extended with additional context, or turned into a panic by the caller.
+#[derive(Debug)]
+pub struct StorageAssertionReport {
+	/// The failed assertions, in the order that the storages were checked.
+	pub errors: Vec<StorageAssertionError>,
+	/// The writes to undeclared keys, if the action was run in
+	/// [strict mode](crate::AssertableDiffableStorageAction::strict).
+	pub undeclared_writes: Vec<UndeclaredWrite>,
+	/// The result of the action, if it wasn't as expected.
+	pub unexpected_outcome: Option<UnexpectedOutcome>,
+}
+
+impl Display for StorageAssertionReport {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let unexpected_outcome =
+			self.unexpected_outcome.iter().map(|outcome| outcome as &dyn Display);
+		let errors = self.errors.iter().map(|error| error as &dyn Display);
+		let undeclared_writes = self.undeclared_writes.iter().map(|write| write as &dyn Display);
+
+		for (i, error) in unexpected_outcome.chain(errors).chain(undeclared_writes).enumerate() {
+			if i != 0 {
+				f.write_str("\n\n")?;
+			}
+
+			Display::fmt(error, f)?;
+		}
+
+		Ok(())
+	}
+}
+
+impl std::error::Error for StorageAssertionReport {}
+
+/// A failed assertion that has not yet been associated with it's storage. See
+/// [`StorageAssertionError`].
+pub struct AssertionFailure {
+	pub(crate) kind: StorageAssertionErrorKind,
+	pub(crate) expected: Option<Box<dyn Debug + Send + Sync>>,
+	pub(crate) found: Option<Box<dyn Debug + Send + Sync>>,
+}
+
+impl AssertionFailure {
+	pub(crate) fn at(self, storage: StorageId) -> StorageAssertionError {
+		StorageAssertionError {
+			storage,
+			kind: self.kind,
+			expected: self.expected,
+			found: self.found,
+		}
+	}
+}
@@ -0,0 +1,122 @@
+//! Checks of the events deposited by an action. See
+//! [`AssertableDiffableStorageAction::check_events`](crate::AssertableDiffableStorageAction::check_events).
+
+use core::marker::PhantomData;
+
+use frame_support::traits::PalletInfoAccess;
+use sp_std::{boxed::Box, vec::Vec};
+
+use crate::{
+	check_storage::StorageId,
+	error::{StorageAssertionError, StorageAssertionErrorKind},
+};
+
+/// How the events deposited by the action are matched against the expected events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EventMatching {
+	/// Exactly the expected events were deposited, in the same order.
+	#[default]
+	Exact,
+	/// Exactly the expected events were deposited, in any order.
+	Unordered,
+	/// All of the expected events were deposited, in any order, along with any other events.
+	Subset,
+}
+
+/// A check of the events deposited by the action, type erased so that it doesn't need to be part
+/// of the type of the [`StorageChecker`](crate::StorageChecker).
+pub(crate) trait EventsCheck {
+	/// Counts the events deposited before the action.
+	fn before(&mut self);
+
+	/// Asserts that the events deposited after [`Self::before`] match the expected events.
+	fn after(self: Box<Self>) -> Option<StorageAssertionError>;
+
+	/// The raw key prefixes of the storages of `frame_system` written to when depositing events,
+	/// along with the storage they belong to.
+	fn declared_prefixes(&self) -> Vec<(StorageId, Vec<u8>)>;
+}
+
+pub(crate) struct Events<T: frame_system::Config> {
+	expected: Vec<T::RuntimeEvent>,
+	matching: EventMatching,
+	deposited_before: usize,
+	_marker: PhantomData<fn() -> T>,
+}
+
+impl<T: frame_system::Config> Events<T> {
+	pub(crate) fn new(expected: Vec<T::RuntimeEvent>, matching: EventMatching) -> Self {
+		Self { expected, matching, deposited_before: 0, _marker: PhantomData }
+	}
+}
+
+impl<T: frame_system::Config> EventsCheck for Events<T> {
+	fn before(&mut self) {
+		self.deposited_before = frame_system::Pallet::<T>::events().len();
+	}
+
+	fn after(self: Box<Self>) -> Option<StorageAssertionError> {
+		let found = frame_system::Pallet::<T>::events()
+			.into_iter()
+			.skip(self.deposited_before)
+			.map(|record| record.event)
+			.collect::<Vec<_>>();
+
+		let matches = match self.matching {
+			EventMatching::Exact => found == self.expected,
+			EventMatching::Unordered => {
+				found.len() == self.expected.len() && contains_all(&found, &self.expected)
+			},
+			EventMatching::Subset => contains_all(&found, &self.expected),
+		};
+
+		if matches {
+			return None;
+		}
+
+		let kind = match (self.expected.is_empty(), found.is_empty()) {
+			(true, _) => StorageAssertionErrorKind::UnexpectedChange,
+			(false, true) => StorageAssertionErrorKind::MissingChange,
+			(false, false) => StorageAssertionErrorKind::Mismatch,
+		};
+
+		Some(StorageAssertionError {
+			storage: events_storage::<T>("Events"),
+			kind,
+			expected: Some(Box::new(self.expected)),
+			found: Some(Box::new(found)),
+		})
+	}
+
+	fn declared_prefixes(&self) -> Vec<(StorageId, Vec<u8>)> {
+		// `frame_system` doesn't expose it's storage types, so their ids are built from the name of
+		// the pallet instead
+		["Events", "EventCount", "EventTopics"]
+			.into_iter()
+			.map(|storage| {
+				let storage = events_storage::<T>(storage);
+				(storage, storage.prefix().to_vec())
+			})
+			.collect()
+	}
+}
+
+/// The [`StorageId`] of the `frame_system` storage `storage`.
+fn events_storage<T: frame_system::Config>(storage: &'static str) -> StorageId {
+	StorageId { pallet: <frame_system::Pallet<T> as PalletInfoAccess>::name(), storage }
+}
+
+/// Whether or not every event in `expected` is in `found`, counting duplicates.
+fn contains_all<E: PartialEq>(found: &[E], expected: &[E]) -> bool {
+	let mut remaining = found.iter().collect::<Vec<_>>();
+
+	expected
+		.iter()
+		.all(|event| match remaining.iter().position(|found| *found == event) {
+			Some(i) => {
+				remaining.swap_remove(i);
+				true
+			},
+			None => false,
+		})
+}
@@ -0,0 +1,159 @@
+use core::{
+	fmt::{self, Debug},
+	ops::RangeBounds,
+};
+
+use sp_std::boxed::Box;
+
+/// The expected change of a storage.
+///
+/// This is either an exact [`Diffable::ChangeSet`](crate::diff::Diffable::ChangeSet), compared
+/// with [`PartialEq`], or a [`Matcher`] for when the exact change isn't known or isn't relevant to
+/// the test. Any `ChangeSet` can be converted into an [`Expectation::Exact`], so
+/// [`check_storage`](crate::AssertableDiffableStorageAction::check_storage) accepts both:
+///
+/// ```rust,ignore
+/// do_action::<(Something<T>, (Other<T>, ())), _, _>(|| { ... })
+///     .check_storage::<Something<T>, _>(OptionDiff::WasNoneNowSome(42))
+///     .check_storage::<Other<T>, _>(any_change())
+///     .assert_storage_changes();
+/// ```
+pub enum Expectation<T> {
+	/// The change must be equal to the contained value.
+	Exact(T),
+	/// The change must satisfy the contained matcher.
+	Matches(Matcher<T>),
+}
+
+impl<T> From<T> for Expectation<T> {
+	fn from(change: T) -> Self {
+		Self::Exact(change)
+	}
+}
+
+impl<T: PartialEq> Expectation<T> {
+	/// Whether or not the found `change` satisfies this expectation.
+	pub fn is_satisfied_by(&self, change: &T) -> bool {
+		match self {
+			Self::Exact(expected) => expected == change,
+			Self::Matches(matcher) => matcher.matches(change),
+		}
+	}
+}
+
+impl<T: Debug> Debug for Expectation<T> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Exact(expected) => expected.fmt(f),
+			Self::Matches(matcher) => matcher.fmt(f),
+		}
+	}
+}
+
+/// A predicate on a [`Diffable::ChangeSet`](crate::diff::Diffable::ChangeSet), along with a
+/// description of what it matches to be used in the output of failed assertions.
+pub struct Matcher<T> {
+	description: String,
+	predicate: Box<dyn Fn(&T) -> bool + Send + Sync>,
+}
+
+impl<T> Matcher<T> {
+	/// Creates a new matcher. The description should describe what is expected of the change,
+	/// i.e. "a value greater than 10".
+	pub fn new(
+		description: impl Into<String>,
+		predicate: impl Fn(&T) -> bool + Send + Sync + 'static,
+	) -> Self {
+		Self { description: description.into(), predicate: Box::new(predicate) }
+	}
+
+	#[must_use]
+	pub fn description(&self) -> &str {
+		&self.description
+	}
+
+	pub fn matches(&self, change: &T) -> bool {
+		(self.predicate)(change)
+	}
+}
+
+impl<T> Debug for Matcher<T> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "<{}>", self.description)
+	}
+}
+
+/// Expect the storage to have changed, without any expectations about the change itself.
+#[must_use]
+pub fn any_change<T>() -> Expectation<T> {
+	matching("any change", |_| true)
+}
+
+/// Expect the change to satisfy the provided predicate.
+///
+/// See also [`matches_pattern!`](crate::matches_pattern), which uses the pattern itself as the
+/// description.
+pub fn matching<T>(
+	description: impl Into<String>,
+	predicate: impl Fn(&T) -> bool + Send + Sync + 'static,
+) -> Expectation<T> {
+	Expectation::Matches(Matcher::new(description, predicate))
+}
+
+/// Expect the change to be within the provided range:
+///
+/// ```rust,ignore
+/// // "changed to a value greater than 10"
+/// .check_storage::<Something<T>, _>(in_range(11..))
+/// ```
+pub fn in_range<T, R>(range: R) -> Expectation<T>
+where
+	T: PartialOrd,
+	R: RangeBounds<T> + Debug + Send + Sync + 'static,
+{
+	matching(format!("a value in the range {range:?}"), move |change| range.contains(change))
+}
+
+/// Expect the change to match the provided pattern, with an optional guard:
+///
+/// ```rust,ignore
+/// .check_storage::<Something<T>, _>(matches_pattern!(OptionDiff::WasNoneNowSome(_)))
+/// .check_storage::<Other<T>, _>(matches_pattern!(OptionDiff::ValueChanged(x) if *x > 10))
+/// ```
+#[macro_export]
+macro_rules! matches_pattern {
+	($pattern:pat $(if $guard:expr)? $(,)?) => {
+		$crate::expectation::matching(
+			stringify!($pattern $(if $guard)?),
+			|change| matches!(change, $pattern $(if $guard)?),
+		)
+	};
+}
+
+#[cfg(test)]
+mod test_expectation {
+	use super::{any_change, in_range, Expectation};
+	use crate::diff::OptionDiff;
+
+	#[test]
+	fn test_exact() {
+		let expectation = Expectation::from(3_u32);
+
+		assert!(expectation.is_satisfied_by(&3));
+		assert!(!expectation.is_satisfied_by(&4));
+	}
+
+	#[test]
+	fn test_matchers() {
+		assert!(any_change().is_satisfied_by(&3_u32));
+
+		assert!(in_range(11_u32..).is_satisfied_by(&11));
+		assert!(!in_range(11_u32..).is_satisfied_by(&10));
+
+		let expectation = crate::matches_pattern!(OptionDiff::<u32>::WasNoneNowSome(x) if *x > 10);
+
+		assert!(expectation.is_satisfied_by(&OptionDiff::WasNoneNowSome(42)));
+		assert!(!expectation.is_satisfied_by(&OptionDiff::WasNoneNowSome(1)));
+		assert!(!expectation.is_satisfied_by(&OptionDiff::WasSomeNowNone));
+	}
+}
@@ -1,6 +1,12 @@
 use core::{fmt::Debug, iter, marker::PhantomData};
 
-use crate::{CheckStorage, Diff, Diffable};
+use crate::{
+	check_storage::{Counter, CounterChange, StorageId},
+	error::{AssertionFailure, StorageAssertionError, StorageAssertionErrorKind},
+	expectation::Expectation,
+	recorder::RawChange,
+	CheckStorage, Diff, Diffable,
+};
 
 pub(crate) type Zipped<A, B> = <A as HZippable<B>>::Zipped;
 pub(crate) type Concatenated<A, B> = <A as Concat<B>>::Output;
@@ -134,18 +140,21 @@ impl AssertDiffHList for () {
 	}
 }
 
-impl<Head: PartialEq + Debug, Tail> AssertDiffHList for (Diff<Head>, Tail)
+impl<Head: PartialEq + Debug + Send + Sync + 'static, Tail> AssertDiffHList for (Diff<Head>, Tail)
 where
 	Tail: AssertDiffHList,
 {
-	// Option<String> should be replaced with a better type at some point, but this works for now
-	type AssertionOutput = (Option<String>, Tail::AssertionOutput);
-	type ExpectedChanges = (Head, Tail::ExpectedChanges);
+	type AssertionOutput = (Option<AssertionFailure>, Tail::AssertionOutput);
+	type ExpectedChanges = (Expectation<Head>, Tail::ExpectedChanges);
 
 	fn assert_unchanged(self) -> Self::AssertionOutput {
 		let output = match self.0 {
 			Diff::NotChanged => None,
-			Diff::ChangedTo(change) => Some(format!("Expected no changes, found {change:#?}")),
+			Diff::ChangedTo(change) => Some(AssertionFailure {
+				kind: StorageAssertionErrorKind::UnexpectedChange,
+				expected: None,
+				found: Some(Box::new(change)),
+			}),
 		};
 
 		(output, self.1.assert_unchanged())
@@ -156,29 +165,38 @@ where
 		expected: Self::ExpectedChanges,
 	) -> Self::AssertionOutput {
 		let output = match self.0 {
-			Diff::NotChanged => {
-				Some(format!("expected change of {:#?}, found no changes", expected.0))
-			},
+			Diff::NotChanged => Some(AssertionFailure {
+				kind: StorageAssertionErrorKind::MissingChange,
+				expected: Some(Box::new(expected.0)),
+				found: None,
+			}),
 			Diff::ChangedTo(change) => {
-				if change == expected.0 {
+				if expected.0.is_satisfied_by(&change) {
 					None
 				} else {
-					Some(format!("expected change of {:#?}, found {change:#?}", expected.0))
+					Some(AssertionFailure {
+						kind: StorageAssertionErrorKind::Mismatch,
+						expected: Some(Box::new(expected.0)),
+						found: Some(Box::new(change)),
+					})
 				}
 			},
 		};
 
-		(output, self.1.assert_unchanged())
+		(output, self.1.assert_changes_are_as_expected(expected.1))
 	}
 }
 
 /// The type of the [`HList`]'s [`Diffable::ChangeSet`].
 pub trait DiffableHList {
 	type ChangeSet;
+	/// The [`Expectation`]s of the [`HList`]'s [`Diffable::ChangeSet`].
+	type Expectations;
 }
 
 impl DiffableHList for () {
 	type ChangeSet = ();
+	type Expectations = ();
 }
 
 impl<Head: Diffable, Tail> DiffableHList for (Head, Tail)
@@ -186,6 +204,8 @@ where
 	Tail: DiffableHList,
 {
 	type ChangeSet = (<Head as Diffable>::ChangeSet, <Tail as DiffableHList>::ChangeSet);
+	type Expectations =
+		(Expectation<<Head as Diffable>::ChangeSet>, <Tail as DiffableHList>::Expectations);
 }
 
 // REVIEW(benluelo): Should this be generic? Or should it have an `Item` associated type?
@@ -218,7 +238,7 @@ where
 /// will be checked by the [`AssertableDiffableStorageAction`].
 pub trait PalletStorageHList: HList {
 	type NamesOutput;
-	type CurrentValue: DiffableHList<ChangeSet = ExpectedChangesOf<Self>>;
+	type CurrentValue: DiffableHList<Expectations = ExpectedChangesOf<Self>>;
 	type Diff: AssertDiffHList;
 
 	/// The names of the storages in this [`HList`].
@@ -227,6 +247,24 @@ pub trait PalletStorageHList: HList {
 	fn current_value() -> Self::CurrentValue;
 
 	fn diff_storage_changes_with_expected_changes(expected: Self::CurrentValue) -> Self::Diff;
+
+	/// The [`CheckStorage::diff_recorded_changes`] of the storages in this [`HList`].
+	fn diff_recorded_changes(changes: &[RawChange]) -> Self::Diff;
+
+	/// The [`CheckStorage::counter`]s of the storages in this [`HList`] that keep one, along with
+	/// the storage they belong to.
+	fn counters() -> Vec<(StorageId, Counter)>;
+
+	/// The [`CheckStorage::counter_change`]s of the storages in this [`HList`] over the `changes`,
+	/// along with the storage they belong to.
+	fn counter_changes(changes: &[RawChange]) -> Vec<(StorageId, CounterChange)>;
+
+	/// The [`CheckStorage::undecodable_entries`] of the storages in this [`HList`].
+	fn undecodable_entries(changes: Option<&[RawChange]>) -> Vec<StorageAssertionError>;
+
+	/// The [`CheckStorage::prefixes`] of the storages in this [`HList`], along with the storage
+	/// they belong to.
+	fn declared_prefixes() -> Vec<(StorageId, Vec<u8>)>;
 }
 
 impl PalletStorageHList for () {
@@ -239,17 +277,35 @@ impl PalletStorageHList for () {
 	fn current_value() -> Self::CurrentValue {}
 
 	fn diff_storage_changes_with_expected_changes(_: Self::CurrentValue) -> Self::Diff {}
+
+	fn diff_recorded_changes(_: &[RawChange]) -> Self::Diff {}
+
+	fn counters() -> Vec<(StorageId, Counter)> {
+		Vec::new()
+	}
+
+	fn counter_changes(_: &[RawChange]) -> Vec<(StorageId, CounterChange)> {
+		Vec::new()
+	}
+
+	fn undecodable_entries(_: Option<&[RawChange]>) -> Vec<StorageAssertionError> {
+		Vec::new()
+	}
+
+	fn declared_prefixes() -> Vec<(StorageId, Vec<u8>)> {
+		Vec::new()
+	}
 }
 
 impl<Head, Tail> PalletStorageHList for (Head, Tail)
 where
 	Head: CheckStorage,
-	<Head::Value as Diffable>::ChangeSet: Debug,
+	<Head::Value as Diffable>::ChangeSet: Debug + Send + Sync + 'static,
 	Tail: PalletStorageHList,
 {
 	type CurrentValue = (Head::Value, Tail::CurrentValue);
 	type Diff = (Diff<<Head::Value as Diffable>::ChangeSet>, Tail::Diff);
-	type NamesOutput = ((&'static str, &'static str), Tail::NamesOutput);
+	type NamesOutput = (StorageId, Tail::NamesOutput);
 
 	fn names() -> Self::NamesOutput {
 		(Head::name(), Tail::names())
@@ -265,4 +321,40 @@ where
 			Tail::diff_storage_changes_with_expected_changes(expected.1),
 		)
 	}
+
+	fn diff_recorded_changes(changes: &[RawChange]) -> Self::Diff {
+		(Head::diff_recorded_changes(changes), Tail::diff_recorded_changes(changes))
+	}
+
+	fn counters() -> Vec<(StorageId, Counter)> {
+		Head::counter()
+			.map(|counter| (Head::name(), counter))
+			.into_iter()
+			.chain(Tail::counters())
+			.collect()
+	}
+
+	fn counter_changes(changes: &[RawChange]) -> Vec<(StorageId, CounterChange)> {
+		Head::counter_change(changes)
+			.map(|change| (Head::name(), change))
+			.into_iter()
+			.chain(Tail::counter_changes(changes))
+			.collect()
+	}
+
+	fn undecodable_entries(changes: Option<&[RawChange]>) -> Vec<StorageAssertionError> {
+		Head::undecodable_entries(changes)
+			.into_iter()
+			.map(|entry| entry.at(Head::name()))
+			.chain(Tail::undecodable_entries(changes))
+			.collect()
+	}
+
+	fn declared_prefixes() -> Vec<(StorageId, Vec<u8>)> {
+		Head::prefixes()
+			.into_iter()
+			.map(|prefix| (Head::name(), prefix))
+			.chain(Tail::declared_prefixes())
+			.collect()
+	}
 }
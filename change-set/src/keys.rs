@@ -0,0 +1,134 @@
+//! Checks of individual entries of maps. See
+//! [`AssertableDiffableStorageAction::check_storage_keys`](crate::AssertableDiffableStorageAction::check_storage_keys).
+
+use core::{fmt::Debug, marker::PhantomData};
+
+use sp_std::{
+	boxed::Box,
+	collections::{btree_map::BTreeMap, btree_set::BTreeSet},
+	vec::Vec,
+};
+
+use crate::{
+	check_storage::{CheckStorageKeys, OpaqueKey, OpaqueValue, StorageId},
+	diff::{Diff, Diffable},
+	error::{StorageAssertionError, StorageAssertionErrorKind},
+	expectation::Expectation,
+	hlist::AssertDiffHList,
+	recorder::RawChange,
+};
+
+/// A check of some of the entries of a map, type erased so that the checks of different maps can
+/// be stored together.
+pub(crate) trait KeyedCheck {
+	/// Reads the checked entries before the action.
+	fn before(&mut self);
+
+	/// Asserts that the checked entries changed as expected. If the writes of the action were
+	/// `recorded`, also asserts that none of the other entries changed, that all of the written
+	/// entries can be decoded, and that the counter of the map changed by as much as it's entries
+	/// did.
+	fn after(self: Box<Self>, recorded: Option<&[RawChange]>) -> Vec<StorageAssertionError>;
+
+	/// The [`CheckStorage::prefixes`] of the map, along with the map they belong to.
+	fn declared_prefixes(&self) -> Vec<(StorageId, Vec<u8>)>;
+}
+
+pub(crate) struct Keyed<S: CheckStorageKeys> {
+	keys: Vec<S::Key>,
+	expected: Expectation<<S::Value as Diffable>::ChangeSet>,
+	before: S::Value,
+	_marker: PhantomData<fn() -> S>,
+}
+
+impl<S: CheckStorageKeys> Keyed<S> {
+	pub(crate) fn new(
+		keys: Vec<S::Key>,
+		expected: Expectation<<S::Value as Diffable>::ChangeSet>,
+	) -> Self {
+		Self { keys, expected, before: BTreeMap::new(), _marker: PhantomData }
+	}
+
+	fn entries(&self) -> S::Value {
+		self.keys
+			.iter()
+			.filter_map(|key| S::entry(key).map(|entry| (key.clone(), entry)))
+			.collect()
+	}
+}
+
+impl<S> KeyedCheck for Keyed<S>
+where
+	S: CheckStorageKeys,
+	<S::Value as Diffable>::ChangeSet: PartialEq + Debug + Send + Sync + 'static,
+{
+	fn before(&mut self) {
+		self.before = self.entries();
+	}
+
+	fn after(self: Box<Self>, recorded: Option<&[RawChange]>) -> Vec<StorageAssertionError> {
+		let after = self.entries();
+		let Self { keys, expected, before, .. } = *self;
+
+		let (failure, ()) = (before.diff(after), ()).assert_changes_are_as_expected((expected, ()));
+
+		let storage = S::name();
+		let failure = failure.map(|failure| failure.at(storage));
+
+		// the rest of the map is never read, so the other entries can only be checked from the
+		// recorded writes
+		let Some(recorded) = recorded else {
+			return failure.into_iter().collect();
+		};
+
+		let prefixes = S::prefixes();
+		let changes = recorded
+			.iter()
+			.filter(|change| change.is_under(&prefixes))
+			.cloned()
+			.collect::<Vec<_>>();
+
+		let checked = keys.iter().map(S::hashed_key).collect::<BTreeSet<_>>();
+		let prefix = storage.prefix();
+
+		// the other prefixes of the map (i.e. it's counter) are checked by the counter check below
+		let mut others_before = BTreeMap::new();
+		let mut others_after = BTreeMap::new();
+
+		for change in changes.iter().filter(|change| !checked.contains(&change.key)) {
+			if let Some(key) = change.key_under(&prefix) {
+				let entry = |value: &Option<Vec<u8>>| {
+					value.clone().map(|value| (OpaqueKey(key.to_vec()), OpaqueValue(value)))
+				};
+
+				others_before.extend(entry(&change.before));
+				others_after.extend(entry(&change.after));
+			}
+		}
+
+		let other_changes = match others_before.diff(others_after) {
+			Diff::NotChanged => None,
+			Diff::ChangedTo(change) => Some(StorageAssertionError {
+				storage,
+				kind: StorageAssertionErrorKind::UnexpectedChange,
+				expected: None,
+				found: Some(Box::new(change)),
+			}),
+		};
+
+		failure
+			.into_iter()
+			.chain(other_changes)
+			.chain(
+				S::undecodable_entries(Some(&changes))
+					.into_iter()
+					.map(|entry| entry.at(storage)),
+			)
+			.chain(S::counter_change(&changes).and_then(|change| change.drift(storage)))
+			.collect()
+	}
+
+	fn declared_prefixes(&self) -> Vec<(StorageId, Vec<u8>)> {
+		S::prefixes().into_iter().map(|prefix| (S::name(), prefix)).collect()
+	}
+}
@@ -2,22 +2,46 @@
 #![allow(clippy::module_name_repetitions)]
 #![deny(warnings)]
 
+// allows the paths generated by `#[derive(Diffable)]` to resolve within this crate
+extern crate self as change_set;
+
 use core::{fmt::Debug, marker::PhantomData};
 
+use frame_support::traits::PalletInfoAccess;
 use hlist::HListIntoIterator;
+use sp_runtime::DispatchError;
 
 use crate::{
-	check_storage::CheckStorage,
+	check_storage::{CheckStorage, CheckStorageKeys, StorageId},
 	diff::{Diff, Diffable},
+	error::{AssertionFailure, StorageAssertionError, StorageAssertionReport, UndeclaredWrite},
+	events::{EventMatching, Events, EventsCheck},
+	expectation::Expectation,
 	hlist::{
 		AssertDiffHList, AssertionOutputOf, Concat, Concatenated, ExpectedChangesOf, Find, HList,
 		HZippable, PalletStorageHList, Zipped,
 	},
+	keys::{Keyed, KeyedCheck},
+	outcome::{
+		AllowsChanges, CheckOutcome, DispatchOutcome, ExpectErr, ExpectOk, NoExpectedOutcome,
+	},
+	runtime::{AllPalletStorages, PalletStorage},
+	strict::{Snapshot, Strict},
 };
 
 pub mod check_storage;
+pub mod child;
 pub mod diff;
+pub mod error;
+pub mod events;
+pub mod expectation;
 mod hlist;
+mod keys;
+pub mod outcome;
+pub mod recorder;
+pub mod render;
+pub mod runtime;
+mod strict;
 
 // name is bikeshedding lol
 pub struct AssertableDiffableStorageAction<
@@ -25,9 +49,24 @@ pub struct AssertableDiffableStorageAction<
 	CheckedStorages: PalletStorageHList,
 	F: FnOnce() -> R,
 	R,
+	Outcome = (),
 > {
 	f: F,
 	storage_checker: StorageChecker<UncheckedStorages, CheckedStorages>,
+	/// The expected result of the action. See [`outcome`].
+	outcome: Outcome,
+}
+
+/// Maps any type to the empty list of storages. Used by the `PalletStorages<T>` generated by
+/// `test_builder::aggregate_storages` for pallets without any storages, as a type alias must use
+/// all of it's type parameters.
+#[doc(hidden)]
+pub trait NoStorages {
+	type Storages: PalletStorageHList;
+}
+
+impl<T: ?Sized> NoStorages for PhantomData<T> {
+	type Storages = ();
 }
 
 /// Takes an "action" as a closure and wraps it in an [`AssertableDiffableStorageAction`].
@@ -37,41 +76,311 @@ pub fn do_action<UncheckedStorages: PalletStorageHList, F: FnOnce() -> R, R>(
 ) -> AssertableDiffableStorageAction<UncheckedStorages, (), F, R> {
 	AssertableDiffableStorageAction {
 		f,
-		storage_checker: StorageChecker { expected_changes: (), _marker: PhantomData },
+		storage_checker: StorageChecker {
+			expected_changes: (),
+			keyed_checks: Vec::new(),
+			events: None,
+			strict: None,
+			_marker: PhantomData,
+		},
+		outcome: (),
 	}
 }
 
-impl<CheckedStorages, UncheckedStorages, F, R>
-	AssertableDiffableStorageAction<UncheckedStorages, CheckedStorages, F, R>
+impl<CheckedStorages, UncheckedStorages, F, R, Outcome>
+	AssertableDiffableStorageAction<UncheckedStorages, CheckedStorages, F, R, Outcome>
 where
 	UncheckedStorages: PalletStorageHList,
 	CheckedStorages: PalletStorageHList,
 	F: FnOnce() -> R,
 {
+	/// Expect the storage `T` to have changed. `expected` can either be the exact
+	/// [`Diffable::ChangeSet`], or any other [`Expectation`] such as
+	/// [`any_change`](expectation::any_change).
 	#[must_use = "check_storage does nothing on it's own, assert_storage_changes must be called to actually do the checks"]
 	pub fn check_storage<T: CheckStorage, Index>(
 		self,
-		t_value: <T::Value as Diffable>::ChangeSet,
+		expected: impl Into<Expectation<<T::Value as Diffable>::ChangeSet>>,
 	) -> AssertableDiffableStorageAction<
 		<UncheckedStorages as Find<T, Index>>::Remainder,
 		(T, CheckedStorages),
 		F,
 		R,
+		Outcome,
 	>
 	where
-		<T::Value as Diffable>::ChangeSet: Debug,
+		Outcome: AllowsChanges,
+		<T::Value as Diffable>::ChangeSet: Debug + Send + Sync + 'static,
 		UncheckedStorages: Find<T, Index>,
 		<UncheckedStorages as Find<T, Index>>::Remainder: PalletStorageHList,
 	{
 		AssertableDiffableStorageAction {
 			f: self.f,
-			storage_checker: self.storage_checker.check_storage(t_value),
+			storage_checker: self.storage_checker.check_storage(expected.into()),
+			outcome: self.outcome,
+		}
+	}
+
+	/// Expect only the entries at `keys` of the map `T` to have changed, without reading the rest
+	/// of the map.
+	///
+	/// The other entries of the map can only be checked when the action is run within
+	/// [`execute_with_recording`](recorder::execute_with_recording), from the recorded writes of
+	/// the action. Any changes to them are then reported as an
+	/// [`UnexpectedChange`](error::StorageAssertionErrorKind::UnexpectedChange) of the map, and the
+	/// counter of the map, if it keeps one, is checked as usual. Without recording, only the
+	/// entries at `keys` are read and checked.
+	///
+	/// ```rust,ignore
+	/// do_action::<PalletStorages<T>, _, _>(|| { ... })
+	///     .check_storage_keys::<Balances<T>, _>([ALICE, BOB], any_change())
+	///     .assert_storage_changes();
+	/// ```
+	#[must_use = "check_storage_keys does nothing on it's own, assert_storage_changes must be called to actually do the checks"]
+	pub fn check_storage_keys<T, Index>(
+		mut self,
+		keys: impl IntoIterator<Item = T::Key>,
+		expected: impl Into<Expectation<<T::Value as Diffable>::ChangeSet>>,
+	) -> AssertableDiffableStorageAction<
+		<UncheckedStorages as Find<T, Index>>::Remainder,
+		CheckedStorages,
+		F,
+		R,
+		Outcome::WithCheckedKeys,
+	>
+	where
+		Outcome: AllowsChanges,
+		T: CheckStorageKeys + 'static,
+		<T::Value as Diffable>::ChangeSet: PartialEq + Debug + Send + Sync + 'static,
+		UncheckedStorages: Find<T, Index>,
+		<UncheckedStorages as Find<T, Index>>::Remainder: PalletStorageHList,
+	{
+		self.storage_checker
+			.keyed_checks
+			.push(Box::new(Keyed::<T>::new(keys.into_iter().collect(), expected.into())));
+
+		AssertableDiffableStorageAction {
+			f: self.f,
+			storage_checker: StorageChecker {
+				expected_changes: self.storage_checker.expected_changes,
+				keyed_checks: self.storage_checker.keyed_checks,
+				events: self.storage_checker.events,
+				strict: self.storage_checker.strict,
+				_marker: PhantomData,
+			},
+			outcome: self.outcome.with_checked_keys(),
+		}
+	}
+
+	/// Replaces the [`PalletStorage`] of the pallet `P` with the typed `Storages` of that pallet
+	/// (usually it's generated `PalletStorages<T>`), so that it's storages can be checked
+	/// individually when checking all of the storages of a runtime. See [`runtime`].
+	///
+	/// ```rust,ignore
+	/// do_action::<RuntimeStorages<Runtime>, _, _>(|| { ... })
+	///     .expand_pallet::<Example, pallet_example::PalletStorages<Runtime>, _>()
+	///     .check_storage::<pallet_example::Something<Runtime>, _>(OptionDiff::WasNoneNowSome(42))
+	///     .assert_storage_changes();
+	/// ```
+	#[must_use = "expand_pallet does nothing on it's own, assert_storage_changes must be called to actually do the checks"]
+	pub fn expand_pallet<P, Storages, Index>(
+		self,
+	) -> AssertableDiffableStorageAction<
+		Concatenated<Storages, <UncheckedStorages as Find<PalletStorage<P>, Index>>::Remainder>,
+		CheckedStorages,
+		F,
+		R,
+		Outcome,
+	>
+	where
+		P: PalletInfoAccess,
+		Storages: PalletStorageHList
+			+ Concat<<UncheckedStorages as Find<PalletStorage<P>, Index>>::Remainder>,
+		UncheckedStorages: Find<PalletStorage<P>, Index>,
+		<UncheckedStorages as Find<PalletStorage<P>, Index>>::Remainder: PalletStorageHList,
+		Concatenated<Storages, <UncheckedStorages as Find<PalletStorage<P>, Index>>::Remainder>:
+			PalletStorageHList,
+	{
+		AssertableDiffableStorageAction {
+			f: self.f,
+			storage_checker: StorageChecker {
+				expected_changes: self.storage_checker.expected_changes,
+				keyed_checks: self.storage_checker.keyed_checks,
+				events: self.storage_checker.events,
+				strict: self.storage_checker.strict,
+				_marker: PhantomData,
+			},
+			outcome: self.outcome,
+		}
+	}
+
+	/// Expect exactly the events `expected` to have been deposited by the action, in order. This
+	/// replaces any previous events check.
+	///
+	/// The deposited events are the events in `frame_system` after the events that were already
+	/// deposited before the action. Note that `frame_system` doesn't deposit any events in the
+	/// genesis block.
+	///
+	/// ```rust,ignore
+	/// do_action::<PalletStorages<T>, _, _>(|| { ... })
+	///     .check_events::<T>([Event::SomethingStored { something: 42, who: ALICE }])
+	///     .assert_storage_changes();
+	/// ```
+	#[must_use = "check_events does nothing on it's own, assert_storage_changes must be called to actually do the checks"]
+	pub fn check_events<T: frame_system::Config>(
+		self,
+		expected: impl IntoIterator<Item = impl Into<T::RuntimeEvent>>,
+	) -> Self {
+		self.check_events_matching::<T>(expected, EventMatching::Exact)
+	}
+
+	/// Same as [`Self::check_events`], but with the deposited events matched against `expected`
+	/// as described by `matching`, i.e. in any order or allowing other events.
+	#[must_use = "check_events_matching does nothing on it's own, assert_storage_changes must be called to actually do the checks"]
+	pub fn check_events_matching<T: frame_system::Config>(
+		mut self,
+		expected: impl IntoIterator<Item = impl Into<T::RuntimeEvent>>,
+		matching: EventMatching,
+	) -> Self {
+		self.storage_checker.events = Some(Box::new(Events::<T>::new(
+			expected.into_iter().map(Into::into).collect(),
+			matching,
+		)));
+		self
+	}
+
+	/// Enables strict mode: all of the keys written to by the action must belong to one of the
+	/// declared storages (either checked or unchecked). Any other writes are reported as
+	/// [`UndeclaredWrite`]s, naming the pallet of the key if it's the pallet of any of the declared
+	/// storages (or of the [known pallets](Self::known_pallets)).
+	///
+	/// Note that this includes writes done by other pallets, such as the events deposited in
+	/// `frame_system`. See [`Self::ignore_pallet`] to allow writes to those pallets. The storages
+	/// of the events are declared by [`Self::check_events`].
+	///
+	/// The writes are found by taking a snapshot of the entire storage before and after the action,
+	/// so this takes time and memory linear in the size of the storage, unless the writes of the
+	/// action are [recorded](recorder) with
+	/// [`execute_with_recording`](recorder::execute_with_recording), in which case only the written
+	/// keys are looked at.
+	///
+	/// Writes to child tries are only found when the writes of the action are
+	/// [recorded](recorder), in which case they must belong to one of the declared
+	/// [child storages](child).
+	#[must_use]
+	pub fn strict(mut self) -> Self {
+		self.storage_checker.strict.get_or_insert_with(Strict::default);
+		self
+	}
+
+	/// Allows writes to any of the storages of `pallet` (the name of the pallet in
+	/// `construct_runtime!`) in strict mode. Enables strict mode if it isn't already enabled.
+	#[must_use]
+	pub fn ignore_pallet(mut self, pallet: &'static str) -> Self {
+		self.storage_checker
+			.strict
+			.get_or_insert_with(Strict::default)
+			.ignored_pallets
+			.push(pallet);
+		self
+	}
+
+	/// Attributes the [`UndeclaredWrite`]s found in strict mode to any of the pallets `P` (usually
+	/// `AllPalletsWithSystem`), rather than only to the pallets of the declared storages. Enables
+	/// strict mode if it isn't already enabled.
+	///
+	/// ```rust,ignore
+	/// do_action::<PalletStorages<T>, _, _>(|| { ... })
+	///     .known_pallets::<AllPalletsWithSystem>()
+	///     .assert_storage_changes();
+	/// ```
+	#[must_use]
+	pub fn known_pallets<P: AllPalletStorages>(mut self) -> Self {
+		self.storage_checker
+			.strict
+			.get_or_insert_with(Strict::default)
+			.known_pallets
+			.extend(P::names());
+		self
+	}
+}
+
+impl<CheckedStorages, UncheckedStorages, F, R, Outcome>
+	AssertableDiffableStorageAction<UncheckedStorages, CheckedStorages, F, R, Outcome>
+where
+	UncheckedStorages: PalletStorageHList,
+	CheckedStorages: PalletStorageHList,
+	F: FnOnce() -> R,
+	R: DispatchOutcome,
+	Outcome: NoExpectedOutcome,
+{
+	/// Expect the action to succeed, i.e. to return `Ok`. The result of the action is still
+	/// returned as is, including any `PostDispatchInfo`.
+	///
+	/// ```rust,ignore
+	/// let post_info = do_action::<PalletStorages<T>, _, _>(|| Pallet::<T>::do_something(origin, 42))
+	///     .expect_ok()
+	///     .check_storage::<Something<T>, _>(OptionDiff::WasNoneNowSome(42))
+	///     .assert_storage_changes()
+	///     .unwrap();
+	/// ```
+	#[must_use = "expect_ok does nothing on it's own, assert_storage_changes must be called to actually do the checks"]
+	pub fn expect_ok(
+		self,
+	) -> AssertableDiffableStorageAction<UncheckedStorages, CheckedStorages, F, R, ExpectOk> {
+		AssertableDiffableStorageAction {
+			f: self.f,
+			storage_checker: self.storage_checker,
+			outcome: ExpectOk,
+		}
+	}
+}
+
+impl<UncheckedStorages, F, R> AssertableDiffableStorageAction<UncheckedStorages, (), F, R>
+where
+	UncheckedStorages: PalletStorageHList,
+	F: FnOnce() -> R,
+	R: DispatchOutcome,
+{
+	/// Expect the action to fail with `error`, and as such to not have changed any of the
+	/// storages. This replaces `assert_noop!`.
+	///
+	/// As a failed dispatch must not have any effect, none of the storages can be expected to
+	/// change: this can't be called after either [`Self::check_storage`] or
+	/// [`Self::check_storage_keys`], and neither of them can be called after this.
+	///
+	/// The action then returns the error it failed with, if any, instead of it's own result.
+	///
+	/// ```rust,ignore
+	/// do_action::<PalletStorages<T>, _, _>(|| Pallet::<T>::cause_error(origin))
+	///     .expect_err(Error::<T>::NoneValue)
+	///     .assert_storage_changes();
+	/// ```
+	#[must_use = "expect_err does nothing on it's own, assert_storage_changes must be called to actually do the checks"]
+	pub fn expect_err(
+		self,
+		error: impl Into<DispatchError>,
+	) -> AssertableDiffableStorageAction<
+		UncheckedStorages,
+		(),
+		impl FnOnce() -> Option<DispatchError>,
+		Option<DispatchError>,
+		ExpectErr,
+	> {
+		let f = self.f;
+
+		// the result of the action is replaced by it's error, as a `DispatchResult` would otherwise
+		// have to be used by the caller after it's already been checked
+		AssertableDiffableStorageAction {
+			f: move || f().dispatch_error(),
+			storage_checker: self.storage_checker,
+			outcome: ExpectErr(error.into()),
 		}
 	}
 }
 
-impl<CheckedStorages, UncheckedStorages, F, R>
-	AssertableDiffableStorageAction<UncheckedStorages, CheckedStorages, F, R>
+impl<CheckedStorages, UncheckedStorages, F, R, Outcome>
+	AssertableDiffableStorageAction<UncheckedStorages, CheckedStorages, F, R, Outcome>
 where
 	UncheckedStorages: PalletStorageHList,
 	CheckedStorages: PalletStorageHList,
@@ -82,13 +391,9 @@ where
 	Concatenated<
 		Zipped<UncheckedStorages::NamesOutput, AssertionOutputOf<UncheckedStorages>>,
 		Zipped<CheckedStorages::NamesOutput, AssertionOutputOf<CheckedStorages>>,
-	>: HListIntoIterator<(
-		// TODO(benluelo): Use a better type here
-		// (pallet prefix, storage prefix)
-		(&'static str, &'static str),
-		Option<String>,
-	)>,
+	>: HListIntoIterator<(StorageId, Option<AssertionFailure>)>,
 	F: FnOnce() -> R,
+	Outcome: CheckOutcome<R>,
 {
 	/// Ties all the magic together!
 	///
@@ -98,24 +403,50 @@ where
 	///
 	/// - Storages not explicitly checked to have a change will be asserted to be unchanged.
 	/// - Storages that were checked are asserted to have the expected changes.
+	/// - Storages that keep a separate count of their entries are asserted to have changed their
+	///   counter by as much as their number of entries. See [`CheckStorage::counter`].
+	/// - The entries of maps are asserted to all be decodable after the action. See
+	///   [`CheckStorage::undecodable_entries`].
+	/// - Maps checked by key are asserted to have the expected changes at those keys, and no
+	///   changes at any of their other keys. See [`Self::check_storage_keys`].
+	/// - The events deposited by the action are asserted to match the expected events, if checked.
+	///   See [`Self::check_events`].
+	/// - The action is asserted to have the expected result, if any. See [`Self::expect_ok`] and
+	///   [`Self::expect_err`].
+	/// - In [strict mode](Self::strict), no keys outside of the declared storages are asserted to
+	///   have been written to.
 	///
 	/// All failed assertions are collected, and this function will panic with the found errors. The
-	/// precise format of this output is subject to change and should not be relied on.
+	/// precise format of this output is subject to change and should not be relied on. See
+	/// [`Self::try_assert_storage_changes`] for a non-panicking version of this function.
 	#[allow(clippy::missing_panics_doc)] // it's supposed to panic
 	pub fn assert_storage_changes(self) -> R {
-		let (r, assertions) = self.storage_checker.check(self.f);
+		self.try_assert_storage_changes().unwrap_or_else(|report| panic!("{report}"))
+	}
 
-		let maybe_errors = assertions
+	/// Same as [`Self::assert_storage_changes`], but returns the failed assertions instead of
+	/// panicking.
+	///
+	/// # Errors
+	///
+	/// If any of the assertions fail, all of the failed assertions are returned in a
+	/// [`StorageAssertionReport`].
+	pub fn try_assert_storage_changes(self) -> Result<R, StorageAssertionReport> {
+		let (r, assertions, other_errors, undeclared_writes) = self.storage_checker.check(self.f);
+
+		let unexpected_outcome = self.outcome.check(&r);
+
+		let errors = assertions
 			.into_iter()
-			.filter_map(|(storage, maybe_error)| maybe_error.map(|error| (storage, error)))
-			.map(|((k1, k2), v)| format!("ERROR at storage {k1}/{k2}: {v}"))
+			.filter_map(|(storage, maybe_failure)| maybe_failure.map(|failure| failure.at(storage)))
+			.chain(other_errors)
 			.collect::<Vec<_>>();
 
-		if !maybe_errors.is_empty() {
-			panic!("{}", maybe_errors.join("\n\n"));
+		if errors.is_empty() && undeclared_writes.is_empty() && unexpected_outcome.is_none() {
+			Ok(r)
+		} else {
+			Err(StorageAssertionReport { errors, undeclared_writes, unexpected_outcome })
 		}
-
-		r
 	}
 }
 
@@ -126,6 +457,9 @@ where
 	CheckedStorages: PalletStorageHList,
 {
 	expected_changes: ExpectedChangesOf<CheckedStorages>,
+	keyed_checks: Vec<Box<dyn KeyedCheck>>,
+	events: Option<Box<dyn EventsCheck>>,
+	strict: Option<Strict>,
 	_marker: PhantomData<fn() -> UncheckedStorages>,
 }
 
@@ -136,15 +470,18 @@ impl<UncheckedStorages: PalletStorageHList, CheckedStorages: PalletStorageHList>
 	/// doing so.
 	pub(crate) fn check_storage<T: CheckStorage, Index>(
 		self,
-		t_value: <T::Value as Diffable>::ChangeSet,
+		expected: Expectation<<T::Value as Diffable>::ChangeSet>,
 	) -> StorageChecker<<UncheckedStorages as Find<T, Index>>::Remainder, (T, CheckedStorages)>
 	where
-		<T::Value as Diffable>::ChangeSet: Debug,
+		<T::Value as Diffable>::ChangeSet: Debug + Send + Sync + 'static,
 		UncheckedStorages: Find<T, Index>,
 		<UncheckedStorages as Find<T, Index>>::Remainder: PalletStorageHList,
 	{
 		StorageChecker {
-			expected_changes: self.expected_changes.prepend(t_value),
+			expected_changes: self.expected_changes.prepend(expected),
+			keyed_checks: self.keyed_checks,
+			events: self.events,
+			strict: self.strict,
 			_marker: PhantomData,
 		}
 	}
@@ -169,6 +506,7 @@ where
 	Zipped<UncheckedStorages::NamesOutput, AssertionOutputOf<UncheckedStorages>>:
 		Concat<Zipped<CheckedStorages::NamesOutput, AssertionOutputOf<CheckedStorages>>>,
 {
+	/// Runs `f` and checks the storages.
 	#[allow(clippy::type_complexity)]
 	pub(crate) fn check<F: FnOnce() -> R, R>(
 		self,
@@ -179,12 +517,60 @@ where
 			Zipped<UncheckedStorages::NamesOutput, AssertionOutputOf<UncheckedStorages>>,
 			Zipped<CheckedStorages::NamesOutput, AssertionOutputOf<CheckedStorages>>,
 		>,
+		Vec<StorageAssertionError>,
+		Vec<UndeclaredWrite>,
 	) {
-		let unchecked_value_before_f = UncheckedStorages::current_value();
-		let checked_value_before_f = CheckedStorages::current_value();
+		let mut keyed_checks = self.keyed_checks;
+		let mut events = self.events;
+
+		// when the writes of `f` are recorded, the storages are only read where they were written
+		// to after `f`, instead of in their entirety both before and after `f`
+		let recording = recorder::reset();
+
+		let values_before_f = (!recording)
+			.then(|| (UncheckedStorages::current_value(), CheckedStorages::current_value()));
+		// counters are checked by how much they changed during `f`, so that drift from before `f`
+		// isn't blamed on it
+		let counters_before_f = (!recording).then(|| {
+			UncheckedStorages::counters()
+				.into_iter()
+				.chain(CheckedStorages::counters())
+				.collect::<Vec<_>>()
+		});
+		for keyed_check in &mut keyed_checks {
+			keyed_check.before();
+		}
+		if let Some(events) = &mut events {
+			events.before();
+		}
+		let snapshot_before_f =
+			self.strict.map(|strict| (strict, (!recording).then(Snapshot::take)));
 
 		let r = f();
 
+		let recorded = recorder::changes();
+
+		let undeclared_writes = snapshot_before_f.map_or_else(Vec::new, |(strict, before)| {
+			let declared = UncheckedStorages::declared_prefixes()
+				.into_iter()
+				.chain(CheckedStorages::declared_prefixes())
+				.chain(keyed_checks.iter().flat_map(|keyed_check| keyed_check.declared_prefixes()))
+				.chain(events.iter().flat_map(|events| events.declared_prefixes()))
+				.collect::<Vec<_>>();
+
+			let changes = match before {
+				Some(before) => before.changes(Snapshot::take()),
+				None => recorded.clone().unwrap_or_default(),
+			};
+
+			strict::undeclared_writes(changes, &declared, &strict)
+		});
+
+		let keyed_errors = keyed_checks
+			.into_iter()
+			.flat_map(|keyed_check| keyed_check.after(recorded.as_deref()))
+			.collect::<Vec<_>>();
+
 		// let expected_unchecked_diff = Diff::NotChanged;
 
 		// let buf = String::new();
@@ -192,22 +578,191 @@ where
 		// map of storage name to storage check error.
 		// let errors = BTreeMap::new();
 
-		// this should be equal to self.input
-		let found_checked_diff =
-			CheckedStorages::diff_storage_changes_with_expected_changes(checked_value_before_f);
+		let (found_checked_diff, unchecked_diff) =
+			if let Some((unchecked_value_before_f, checked_value_before_f)) = values_before_f {
+				(
+					// this should be equal to self.input
+					CheckedStorages::diff_storage_changes_with_expected_changes(
+						checked_value_before_f,
+					),
+					// this should result in no changes, assuming the storages haven't been
+					// changed. if there have been unaccounted for changes, then this will result
+					// in a "failed" diff
+					UncheckedStorages::diff_storage_changes_with_expected_changes(
+						unchecked_value_before_f,
+					),
+				)
+			} else {
+				let recorded = recorded.as_deref().unwrap_or_default();
+
+				(
+					CheckedStorages::diff_recorded_changes(recorded),
+					UncheckedStorages::diff_recorded_changes(recorded),
+				)
+			};
 
 		let checked_assertions = CheckedStorages::names()
 			.zip(found_checked_diff.assert_changes_are_as_expected(self.expected_changes));
 
-		// this should result in no changes, assuming the storages haven't been changed. if there
-		// have been unaccounted for changes, then this will result in a "failed" diff
-		let unchecked_diff =
-			UncheckedStorages::diff_storage_changes_with_expected_changes(unchecked_value_before_f);
-
 		let unchecked_assertions =
 			UncheckedStorages::names().zip(unchecked_diff.assert_unchanged());
 
-		(r, unchecked_assertions.concat(checked_assertions))
+		let counter_changes = if let Some(counters_before_f) = counters_before_f {
+			counters_before_f
+				.into_iter()
+				.zip(UncheckedStorages::counters().into_iter().chain(CheckedStorages::counters()))
+				.map(|((storage, before), (_, after))| (storage, before.change_to(after)))
+				.collect::<Vec<_>>()
+		} else {
+			let recorded = recorded.as_deref().unwrap_or_default();
+
+			UncheckedStorages::counter_changes(recorded)
+				.into_iter()
+				.chain(CheckedStorages::counter_changes(recorded))
+				.collect()
+		};
+
+		let other_errors = counter_changes
+			.into_iter()
+			.filter_map(|(storage, change)| change.drift(storage))
+			.chain(UncheckedStorages::undecodable_entries(recorded.as_deref()))
+			.chain(CheckedStorages::undecodable_entries(recorded.as_deref()))
+			.chain(keyed_errors)
+			.chain(events.and_then(EventsCheck::after))
+			.collect();
+
+		(r, unchecked_assertions.concat(checked_assertions), other_errors, undeclared_writes)
+	}
+}
+
+#[cfg(test)]
+mod test_assert_storage_changes {
+	use core::cell::Cell;
+
+	use frame_support::dispatch::{DispatchResultWithPostInfo, Pays, PostDispatchInfo};
+	use sp_runtime::{DispatchError, DispatchResult};
+
+	use crate::{
+		check_storage::{CheckStorage, StorageId},
+		do_action,
+		error::{StorageAssertionErrorKind, StorageAssertionReport, UnexpectedOutcome},
+		recorder::RawChange,
+	};
+
+	std::thread_local! {
+		static VALUE: Cell<u32> = Cell::new(0);
+	}
+
+	/// A storage backed by [`VALUE`], so that the checks can run without any externalities.
+	struct Value;
+
+	impl CheckStorage for Value {
+		type Value = u32;
+
+		fn name() -> StorageId {
+			StorageId { pallet: "Test", storage: "Value" }
+		}
+
+		fn current_value() -> Self::Value {
+			VALUE.with(Cell::get)
+		}
+
+		fn recorded_values(_: &[RawChange]) -> Option<(Self::Value, Self::Value)> {
+			unreachable!("the writes to `VALUE` can't be recorded")
+		}
+	}
+
+	fn set_value(value: u32) {
+		VALUE.with(|cell| cell.set(value));
+	}
+
+	fn failed(report: &StorageAssertionReport) -> Vec<(StorageId, StorageAssertionErrorKind)> {
+		report.errors.iter().map(|error| (error.storage, error.kind)).collect()
+	}
+
+	#[test]
+	fn test_report_is_send_and_sync() {
+		fn assert_send_sync<T: Send + Sync>() {}
+
+		assert_send_sync::<StorageAssertionReport>();
+	}
+
+	#[test]
+	fn test_unexpected_change() {
+		let report = do_action::<(Value, ()), _, _>(|| set_value(1))
+			.try_assert_storage_changes()
+			.unwrap_err();
+
+		assert_eq!(failed(&report), [(Value::name(), StorageAssertionErrorKind::UnexpectedChange)]);
+		assert!(report.undeclared_writes.is_empty());
+		assert_eq!(report.unexpected_outcome, None);
+	}
+
+	#[test]
+	fn test_mismatch() {
+		let report = do_action::<(Value, ()), _, _>(|| set_value(2))
+			.check_storage::<Value, _>(3)
+			.try_assert_storage_changes()
+			.unwrap_err();
+
+		assert_eq!(failed(&report), [(Value::name(), StorageAssertionErrorKind::Mismatch)]);
+		assert_eq!(format!("{:?}", report.errors[0].found), "Some(2)");
+	}
+
+	#[test]
+	fn test_expected_change() {
+		set_value(0);
+
+		do_action::<(Value, ()), _, _>(|| set_value(4))
+			.check_storage::<Value, _>(4)
+			.try_assert_storage_changes()
+			.unwrap();
+	}
+
+	#[test]
+	fn test_expect_ok_returns_the_result() {
+		let post_info = PostDispatchInfo { actual_weight: None, pays_fee: Pays::No };
+
+		set_value(0);
+
+		let result = do_action::<(Value, ()), _, _>(|| -> DispatchResultWithPostInfo {
+			set_value(5);
+			Ok(post_info)
+		})
+		.expect_ok()
+		.check_storage::<Value, _>(5)
+		.try_assert_storage_changes()
+		.unwrap();
+
+		assert_eq!(result, Ok(post_info));
+	}
+
+	#[test]
+	fn test_unexpected_outcome() {
+		let report = do_action::<(Value, ()), _, _>(|| -> DispatchResult { Ok(()) })
+			.expect_err(DispatchError::BadOrigin)
+			.try_assert_storage_changes()
+			.unwrap_err();
+
+		assert!(report.errors.is_empty());
+		assert_eq!(
+			report.unexpected_outcome,
+			Some(UnexpectedOutcome { expected: Some(DispatchError::BadOrigin), found: None })
+		);
+	}
+
+	#[test]
+	fn test_changes_of_failed_action() {
+		let report = do_action::<(Value, ()), _, _>(|| -> DispatchResult {
+			set_value(6);
+			Err(DispatchError::BadOrigin)
+		})
+		.expect_err(DispatchError::BadOrigin)
+		.try_assert_storage_changes()
+		.unwrap_err();
+
+		assert_eq!(failed(&report), [(Value::name(), StorageAssertionErrorKind::UnexpectedChange)]);
+		assert_eq!(report.unexpected_outcome, None);
 	}
 }
 
@@ -0,0 +1,119 @@
+//! Expectations on the result of dispatching a call. See
+//! [`AssertableDiffableStorageAction::expect_ok`](crate::AssertableDiffableStorageAction::expect_ok)
+//! and
+//! [`AssertableDiffableStorageAction::expect_err`](crate::AssertableDiffableStorageAction::expect_err).
+
+use frame_support::dispatch::DispatchResultWithPostInfo;
+use sp_runtime::{DispatchError, DispatchResult};
+
+use crate::error::UnexpectedOutcome;
+
+/// The result of dispatching a call.
+pub trait DispatchOutcome {
+	/// The error that the dispatch failed with, if it failed.
+	fn dispatch_error(&self) -> Option<DispatchError>;
+}
+
+impl DispatchOutcome for DispatchResult {
+	fn dispatch_error(&self) -> Option<DispatchError> {
+		self.as_ref().err().copied()
+	}
+}
+
+impl DispatchOutcome for DispatchResultWithPostInfo {
+	fn dispatch_error(&self) -> Option<DispatchError> {
+		self.as_ref().err().map(|error| error.error)
+	}
+}
+
+/// An expectation on the result `R` of the action, checked after the action ran. `()` doesn't
+/// expect anything of the result.
+pub trait CheckOutcome<R> {
+	/// Compares the `result` of the action against the expected outcome.
+	fn check(self, result: &R) -> Option<UnexpectedOutcome>;
+}
+
+impl<R> CheckOutcome<R> for () {
+	fn check(self, _: &R) -> Option<UnexpectedOutcome> {
+		None
+	}
+}
+
+/// Expects the action to succeed. See
+/// [`AssertableDiffableStorageAction::expect_ok`](crate::AssertableDiffableStorageAction::expect_ok).
+pub struct ExpectOk;
+
+impl<R: DispatchOutcome> CheckOutcome<R> for ExpectOk {
+	fn check(self, result: &R) -> Option<UnexpectedOutcome> {
+		let found = result.dispatch_error();
+
+		found.is_some().then_some(UnexpectedOutcome { expected: None, found })
+	}
+}
+
+/// Expects the action to fail with the contained error. See
+/// [`AssertableDiffableStorageAction::expect_err`](crate::AssertableDiffableStorageAction::expect_err),
+/// which replaces the result of the action with the error it failed with.
+pub struct ExpectErr(pub(crate) DispatchError);
+
+impl CheckOutcome<Option<DispatchError>> for ExpectErr {
+	fn check(self, found: &Option<DispatchError>) -> Option<UnexpectedOutcome> {
+		(*found != Some(self.0))
+			.then_some(UnexpectedOutcome { expected: Some(self.0), found: *found })
+	}
+}
+
+/// No expected result, like `()`, after some of the entries of a map were checked by key with
+/// [`AssertableDiffableStorageAction::check_storage_keys`](crate::AssertableDiffableStorageAction::check_storage_keys).
+/// Unlike `()`, this can't be followed by
+/// [`AssertableDiffableStorageAction::expect_err`](crate::AssertableDiffableStorageAction::expect_err),
+/// as the checked entries are expected to change.
+pub struct CheckedKeys;
+
+impl<R> CheckOutcome<R> for CheckedKeys {
+	fn check(self, _: &R) -> Option<UnexpectedOutcome> {
+		None
+	}
+}
+
+/// The expected outcomes that the storages can be expected to change with, i.e. all of them but
+/// [`ExpectErr`], as a failed dispatch must not have any effect.
+pub trait AllowsChanges {
+	/// The expected outcome after checking the entries of a map by key.
+	type WithCheckedKeys: AllowsChanges;
+
+	fn with_checked_keys(self) -> Self::WithCheckedKeys;
+}
+
+impl AllowsChanges for () {
+	type WithCheckedKeys = CheckedKeys;
+
+	fn with_checked_keys(self) -> Self::WithCheckedKeys {
+		CheckedKeys
+	}
+}
+
+impl AllowsChanges for CheckedKeys {
+	type WithCheckedKeys = Self;
+
+	fn with_checked_keys(self) -> Self::WithCheckedKeys {
+		self
+	}
+}
+
+impl AllowsChanges for ExpectOk {
+	type WithCheckedKeys = Self;
+
+	fn with_checked_keys(self) -> Self::WithCheckedKeys {
+		self
+	}
+}
+
+/// The expected outcomes that don't expect anything of the result yet, i.e. the ones that
+/// [`AssertableDiffableStorageAction::expect_ok`](crate::AssertableDiffableStorageAction::expect_ok)
+/// can be called with.
+pub trait NoExpectedOutcome {}
+
+impl NoExpectedOutcome for () {}
+
+impl NoExpectedOutcome for CheckedKeys {}
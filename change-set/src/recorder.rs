@@ -0,0 +1,355 @@
+//! Recording of the keys written to during a test, so that the changed keys can be found without
+//! scanning the entire storage.
+//!
+//! While recording, the storages aren't read before the action at all, and after the action only
+//! the written values are decoded from the recorded writes (see
+//! [`CheckStorage::recorded_values`](crate::check_storage::CheckStorage::recorded_values)).
+//! This makes checks scale with the amount of changes made by the action, instead of with the
+//! size of the state.
+//!
+//! Wrap the test in [`execute_with_recording`] instead of `TestExternalities::execute_with`:
+//!
+//! ```rust,ignore
+//! let mut ext = new_test_ext();
+//! execute_with_recording(&mut ext.ext(), || {
+//!     do_action::<PalletStorages<Test>, _, _>(|| { ... })
+//!         .check_storage_keys::<Balances<Test>, _>([ALICE], any_change())
+//!         .assert_storage_changes();
+//! });
+//! ```
+
+use core::any::{Any, TypeId};
+
+use sp_core::storage::{ChildInfo, StateVersion, TrackedStorageKey};
+use sp_externalities::{Error, Extension, ExtensionStore, Externalities, MultiRemovalResults};
+use sp_std::{boxed::Box, collections::btree_map::BTreeMap, vec::Vec};
+
+/// Runs `f` with `ext` as it's externalities, recording all of the keys written to by `f`.
+///
+/// While recording, [`AssertableDiffableStorageAction`](crate::AssertableDiffableStorageAction)
+/// finds the keys written to by the action from the recorded writes instead of by scanning the
+/// storage. Without recording, all of the checks still work, only slower.
+pub fn execute_with_recording<R>(ext: &mut dyn Externalities, f: impl FnOnce() -> R) -> R {
+	let mut recording = RecordingExternalities { inner: ext, log: WriteLog::default() };
+
+	sp_externalities::set_and_run_with_externalities(&mut recording, f)
+}
+
+/// A raw key whose value changed, along with it's value before and after the change.
+///
+/// Keys in child tries are prefixed with the prefixed storage key of their child trie (see
+/// [`ChildInfo::prefixed_storage_key`]), which is also the key of the child trie's root in the
+/// main trie. The prefixed storage key is kept in `child_trie` as well, since the storage key of
+/// one child trie can be a prefix of the storage key of another.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawChange {
+	pub key: Vec<u8>,
+	pub child_trie: Option<Vec<u8>>,
+	pub before: Option<Vec<u8>>,
+	pub after: Option<Vec<u8>>,
+}
+
+impl RawChange {
+	/// Whether or not the key is under any of the `prefixes`.
+	#[must_use]
+	pub fn is_under(&self, prefixes: &[Vec<u8>]) -> bool {
+		prefixes.iter().any(|prefix| self.key_under(prefix).is_some())
+	}
+
+	/// The rest of the key after `prefix`, if the key is under `prefix`. A key in a child trie is
+	/// only under the prefixes that include the entire prefixed storage key of it's child trie, so
+	/// that it isn't mistaken for a key of another child trie whose storage key is a prefix of
+	/// it's own.
+	#[must_use]
+	pub fn key_under(&self, prefix: &[u8]) -> Option<&[u8]> {
+		match &self.child_trie {
+			Some(child_trie) if !prefix.starts_with(child_trie) => None,
+			_ => self.key.strip_prefix(prefix),
+		}
+	}
+}
+
+/// The value of every key written to since the log was last reset, from before the first write.
+#[derive(Debug, Default)]
+struct WriteLog {
+	/// Keyed by the key in the main trie.
+	top: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+	/// Keyed by the storage key of the child trie, and the key in the child trie.
+	child: BTreeMap<(Vec<u8>, Vec<u8>), Option<Vec<u8>>>,
+	/// The child tries in `child`, by their storage key.
+	child_infos: BTreeMap<Vec<u8>, ChildInfo>,
+}
+
+/// Clears the recorded writes. Returns whether or not writes are being recorded.
+pub(crate) fn reset() -> bool {
+	sp_externalities::with_externalities(|ext| {
+		write_log(ext).map(|log| *log = WriteLog::default()).is_some()
+	})
+	.unwrap_or_default()
+}
+
+/// The keys written to since the last [`reset`] whose values changed, ordered by key, or `None` if
+/// writes aren't being recorded.
+pub(crate) fn changes() -> Option<Vec<RawChange>> {
+	sp_externalities::with_externalities(|ext| {
+		let log = write_log(ext)?;
+		let (top, child, child_infos) =
+			(log.top.clone(), log.child.clone(), log.child_infos.clone());
+
+		let top = top.into_iter().map(|(key, before)| RawChange {
+			after: ext.storage(&key),
+			key,
+			child_trie: None,
+			before,
+		});
+
+		let child = child.into_iter().map(|((storage_key, key), before)| {
+			let child_info = &child_infos[&storage_key];
+			let child_trie = child_info.prefixed_storage_key().into_inner();
+
+			RawChange {
+				after: ext.child_storage(child_info, &key),
+				key: [&child_trie[..], &key[..]].concat(),
+				child_trie: Some(child_trie),
+				before,
+			}
+		});
+
+		let mut changes = top
+			.chain(child)
+			.filter(|change| change.before != change.after)
+			.collect::<Vec<_>>();
+		changes.sort_by(|a, b| a.key.cmp(&b.key));
+
+		Some(changes)
+	})
+	.flatten()
+}
+
+/// The [`WriteLog`] of `ext`, if it's [`RecordingExternalities`].
+fn write_log(ext: &mut dyn Externalities) -> Option<&mut WriteLog> {
+	ext.extension_by_type_id(TypeId::of::<WriteLog>())?.downcast_mut()
+}
+
+/// [`Externalities`] that records the original value of every key written to, delegating
+/// everything else to the wrapped externalities.
+struct RecordingExternalities<'a> {
+	inner: &'a mut dyn Externalities,
+	log: WriteLog,
+}
+
+impl RecordingExternalities<'_> {
+	fn record(&mut self, key: &[u8]) {
+		if !self.log.top.contains_key(key) {
+			let before = self.inner.storage(key);
+			self.log.top.insert(key.to_vec(), before);
+		}
+	}
+
+	fn record_child(&mut self, child_info: &ChildInfo, key: &[u8]) {
+		let storage_key = child_info.storage_key().to_vec();
+
+		if !self.log.child.contains_key(&(storage_key.clone(), key.to_vec())) {
+			let before = self.inner.child_storage(child_info, key);
+			self.log.child.insert((storage_key.clone(), key.to_vec()), before);
+			self.log.child_infos.entry(storage_key).or_insert_with(|| child_info.clone());
+		}
+	}
+
+	fn record_prefix(&mut self, prefix: &[u8]) {
+		self.record(prefix);
+
+		let mut previous_key = prefix.to_vec();
+
+		while let Some(key) = self.inner.next_storage_key(&previous_key) {
+			if !key.starts_with(prefix) {
+				break;
+			}
+
+			self.record(&key);
+			previous_key = key;
+		}
+	}
+
+	fn record_child_prefix(&mut self, child_info: &ChildInfo, prefix: &[u8]) {
+		self.record_child(child_info, prefix);
+
+		let mut previous_key = prefix.to_vec();
+
+		while let Some(key) = self.inner.next_child_storage_key(child_info, &previous_key) {
+			if !key.starts_with(prefix) {
+				break;
+			}
+
+			self.record_child(child_info, &key);
+			previous_key = key;
+		}
+	}
+}
+
+impl ExtensionStore for RecordingExternalities<'_> {
+	fn extension_by_type_id(&mut self, type_id: TypeId) -> Option<&mut dyn Any> {
+		if type_id == TypeId::of::<WriteLog>() {
+			Some(&mut self.log)
+		} else {
+			self.inner.extension_by_type_id(type_id)
+		}
+	}
+
+	fn register_extension_with_type_id(
+		&mut self,
+		type_id: TypeId,
+		extension: Box<dyn Extension>,
+	) -> Result<(), Error> {
+		self.inner.register_extension_with_type_id(type_id, extension)
+	}
+
+	fn deregister_extension_by_type_id(&mut self, type_id: TypeId) -> Result<(), Error> {
+		self.inner.deregister_extension_by_type_id(type_id)
+	}
+}
+
+impl Externalities for RecordingExternalities<'_> {
+	fn set_offchain_storage(&mut self, key: &[u8], value: Option<&[u8]>) {
+		self.inner.set_offchain_storage(key, value);
+	}
+
+	fn storage(&self, key: &[u8]) -> Option<Vec<u8>> {
+		self.inner.storage(key)
+	}
+
+	fn storage_hash(&self, key: &[u8]) -> Option<Vec<u8>> {
+		self.inner.storage_hash(key)
+	}
+
+	fn child_storage_hash(&self, child_info: &ChildInfo, key: &[u8]) -> Option<Vec<u8>> {
+		self.inner.child_storage_hash(child_info, key)
+	}
+
+	fn child_storage(&self, child_info: &ChildInfo, key: &[u8]) -> Option<Vec<u8>> {
+		self.inner.child_storage(child_info, key)
+	}
+
+	fn next_storage_key(&self, key: &[u8]) -> Option<Vec<u8>> {
+		self.inner.next_storage_key(key)
+	}
+
+	fn next_child_storage_key(&self, child_info: &ChildInfo, key: &[u8]) -> Option<Vec<u8>> {
+		self.inner.next_child_storage_key(child_info, key)
+	}
+
+	fn kill_child_storage(
+		&mut self,
+		child_info: &ChildInfo,
+		maybe_limit: Option<u32>,
+		maybe_cursor: Option<&[u8]>,
+	) -> MultiRemovalResults {
+		self.record_child_prefix(child_info, &[]);
+		self.inner.kill_child_storage(child_info, maybe_limit, maybe_cursor)
+	}
+
+	fn clear_prefix(
+		&mut self,
+		prefix: &[u8],
+		maybe_limit: Option<u32>,
+		maybe_cursor: Option<&[u8]>,
+	) -> MultiRemovalResults {
+		self.record_prefix(prefix);
+		self.inner.clear_prefix(prefix, maybe_limit, maybe_cursor)
+	}
+
+	fn clear_child_prefix(
+		&mut self,
+		child_info: &ChildInfo,
+		prefix: &[u8],
+		maybe_limit: Option<u32>,
+		maybe_cursor: Option<&[u8]>,
+	) -> MultiRemovalResults {
+		self.record_child_prefix(child_info, prefix);
+		self.inner.clear_child_prefix(child_info, prefix, maybe_limit, maybe_cursor)
+	}
+
+	fn place_storage(&mut self, key: Vec<u8>, value: Option<Vec<u8>>) {
+		self.record(&key);
+		self.inner.place_storage(key, value);
+	}
+
+	fn place_child_storage(
+		&mut self,
+		child_info: &ChildInfo,
+		key: Vec<u8>,
+		value: Option<Vec<u8>>,
+	) {
+		self.record_child(child_info, &key);
+		self.inner.place_child_storage(child_info, key, value);
+	}
+
+	fn storage_root(&mut self, state_version: StateVersion) -> Vec<u8> {
+		self.inner.storage_root(state_version)
+	}
+
+	fn child_storage_root(
+		&mut self,
+		child_info: &ChildInfo,
+		state_version: StateVersion,
+	) -> Vec<u8> {
+		self.inner.child_storage_root(child_info, state_version)
+	}
+
+	fn storage_append(&mut self, key: Vec<u8>, value: Vec<u8>) {
+		self.record(&key);
+		self.inner.storage_append(key, value);
+	}
+
+	fn storage_start_transaction(&mut self) {
+		self.inner.storage_start_transaction();
+	}
+
+	fn storage_rollback_transaction(&mut self) -> Result<(), ()> {
+		self.inner.storage_rollback_transaction()
+	}
+
+	fn storage_commit_transaction(&mut self) -> Result<(), ()> {
+		self.inner.storage_commit_transaction()
+	}
+
+	fn wipe(&mut self) {
+		self.inner.wipe();
+	}
+
+	fn commit(&mut self) {
+		self.inner.commit();
+	}
+
+	fn read_write_count(&self) -> (u32, u32, u32, u32) {
+		self.inner.read_write_count()
+	}
+
+	fn reset_read_write_count(&mut self) {
+		self.inner.reset_read_write_count();
+	}
+
+	fn get_whitelist(&self) -> Vec<TrackedStorageKey> {
+		self.inner.get_whitelist()
+	}
+
+	fn set_whitelist(&mut self, new: Vec<TrackedStorageKey>) {
+		self.inner.set_whitelist(new);
+	}
+
+	fn proof_size(&self) -> Option<u32> {
+		self.inner.proof_size()
+	}
+
+	fn get_read_and_written_keys(&self) -> Vec<(Vec<u8>, u32, u32, bool)> {
+		self.inner.get_read_and_written_keys()
+	}
+
+	fn storage_index_transaction(&mut self, index: u32, hash: &[u8], size: u32) {
+		self.inner.storage_index_transaction(index, hash, size);
+	}
+
+	fn storage_renew_transaction_index(&mut self, index: u32, hash: &[u8]) {
+		self.inner.storage_renew_transaction_index(index, hash);
+	}
+}
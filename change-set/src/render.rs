@@ -0,0 +1,311 @@
+//! Rendering of failed storage assertions.
+//!
+//! The expected and found changes are rendered as a line-level diff of their pretty-printed
+//! ([`{:#?}`](core::fmt::Debug)) output, so that a single differing entry in a large map doesn't
+//! have to be found by comparing two blobs by eye. Long runs of identical lines (i.e. the
+//! unchanged parts of nested maps) are collapsed, keeping only a few lines of context around each
+//! difference.
+//!
+//! Output is colored with ANSI escape codes, unless the `NO_COLOR` environment variable is set to a
+//! non-empty value (see <https://no-color.org>).
+
+use core::fmt::Write;
+
+use sp_std::vec::Vec;
+
+use crate::{
+	diff::{lcs_edits, Edit},
+	error::{StorageAssertionError, StorageAssertionErrorKind},
+};
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const DIM: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+
+/// How the expected and found changes are laid out relative to each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Layout {
+	/// A single column, with expected lines prefixed by `-` and found lines prefixed by `+`.
+	#[default]
+	Unified,
+	/// Expected on the left, found on the right.
+	SideBySide,
+}
+
+/// Renders [`StorageAssertionError`]s. The [`Display`](core::fmt::Display) impl of
+/// [`StorageAssertionError`] uses [`Renderer::from_env`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Renderer {
+	/// Whether or not to color the output with ANSI escape codes.
+	pub color: bool,
+	pub layout: Layout,
+	/// The amount of identical lines kept around each difference; any more are collapsed.
+	pub context: usize,
+}
+
+impl Default for Renderer {
+	fn default() -> Self {
+		Self::from_env()
+	}
+}
+
+impl Renderer {
+	/// A [`Layout::Unified`] renderer, colored unless `NO_COLOR` is set.
+	#[must_use]
+	pub fn from_env() -> Self {
+		Self {
+			color: !matches!(std::env::var_os("NO_COLOR"), Some(value) if !value.is_empty()),
+			layout: Layout::Unified,
+			context: 3,
+		}
+	}
+
+	#[must_use]
+	pub fn render(&self, error: &StorageAssertionError) -> String {
+		let expected = error.expected.as_ref().map(|expected| format!("{expected:#?}"));
+		let found = error.found.as_ref().map(|found| format!("{found:#?}"));
+
+		let summary = match error.kind {
+			StorageAssertionErrorKind::UnexpectedChange => "expected no changes, found",
+			StorageAssertionErrorKind::MissingChange => "expected change, found no changes",
+			StorageAssertionErrorKind::Mismatch => "expected change did not match found change",
+			StorageAssertionErrorKind::CounterDrift => "counter drifted from the number of entries",
+			StorageAssertionErrorKind::Undecodable => "found an entry that could not be decoded",
+		};
+
+		if expected.is_none() && found.is_none() {
+			return format!("ERROR at storage {}: {summary}", error.storage);
+		}
+
+		format!(
+			"ERROR at storage {}: {summary} ({}, {}):\n{}",
+			error.storage,
+			self.paint(RED, "- expected"),
+			self.paint(GREEN, "+ found"),
+			self.render_diff(
+				expected.as_deref().unwrap_or_default(),
+				found.as_deref().unwrap_or_default()
+			)
+		)
+	}
+
+	/// Renders a line-level diff of `expected` and `found`.
+	#[must_use]
+	pub fn render_diff(&self, expected: &str, found: &str) -> String {
+		let hunks = self.collapse(hunks(expected, found));
+
+		match self.layout {
+			Layout::Unified => self.render_unified(&hunks),
+			Layout::SideBySide => self.render_side_by_side(&hunks),
+		}
+	}
+
+	fn render_unified(&self, hunks: &[Hunk<'_>]) -> String {
+		let mut out = String::new();
+
+		for hunk in hunks {
+			match hunk {
+				Hunk::Same(lines) => {
+					for line in lines {
+						push_line(&mut out, &format!("  {line}"));
+					}
+				},
+				Hunk::Collapsed(count) => push_line(&mut out, &self.collapsed(*count)),
+				Hunk::Changed { expected, found } => {
+					for line in expected {
+						push_line(&mut out, &self.paint(RED, &format!("- {line}")));
+					}
+					for line in found {
+						push_line(&mut out, &self.paint(GREEN, &format!("+ {line}")));
+					}
+				},
+			}
+		}
+
+		out
+	}
+
+	fn render_side_by_side(&self, hunks: &[Hunk<'_>]) -> String {
+		let width = hunks
+			.iter()
+			.flat_map(|hunk| match hunk {
+				Hunk::Same(lines) | Hunk::Changed { expected: lines, .. } => lines.as_slice(),
+				Hunk::Collapsed(_) => &[],
+			})
+			.map(|line| line.chars().count())
+			.max()
+			.unwrap_or_default();
+
+		let mut out = String::new();
+
+		for hunk in hunks {
+			match hunk {
+				Hunk::Same(lines) => {
+					for line in lines {
+						push_line(&mut out, &format!("  {line:<width$} |   {line}"));
+					}
+				},
+				Hunk::Collapsed(count) => push_line(&mut out, &self.collapsed(*count)),
+				Hunk::Changed { expected, found } => {
+					for row in 0..expected.len().max(found.len()) {
+						let left = expected.get(row).map_or_else(
+							|| format!("  {:width$}", ""),
+							|line| self.paint(RED, &format!("- {line:<width$}")),
+						);
+						let right = found
+							.get(row)
+							.map(|line| self.paint(GREEN, &format!("+ {line}")))
+							.unwrap_or_default();
+
+						push_line(&mut out, format!("{left} | {right}").trim_end());
+					}
+				},
+			}
+		}
+
+		out
+	}
+
+	/// Replaces the middle of long runs of identical lines with [`Hunk::Collapsed`], keeping
+	/// [`Self::context`] lines next to each change.
+	fn collapse<'a>(&self, hunks: Vec<Hunk<'a>>) -> Vec<Hunk<'a>> {
+		let last = hunks.len().saturating_sub(1);
+		let mut collapsed = Vec::with_capacity(hunks.len());
+
+		for (i, hunk) in hunks.into_iter().enumerate() {
+			let Hunk::Same(lines) = hunk else {
+				collapsed.push(hunk);
+				continue;
+			};
+
+			let before = if i == 0 { 0 } else { self.context };
+			let after = if i == last { 0 } else { self.context };
+
+			// collapsing a single line would only replace it with the marker
+			if lines.len() <= before + after + 1 {
+				collapsed.push(Hunk::Same(lines));
+				continue;
+			}
+
+			if before != 0 {
+				collapsed.push(Hunk::Same(lines[..before].to_vec()));
+			}
+			collapsed.push(Hunk::Collapsed(lines.len() - before - after));
+			if after != 0 {
+				collapsed.push(Hunk::Same(lines[lines.len() - after..].to_vec()));
+			}
+		}
+
+		collapsed
+	}
+
+	fn collapsed(&self, count: usize) -> String {
+		self.paint(DIM, &format!("  ... {count} identical lines ..."))
+	}
+
+	fn paint(&self, color: &str, text: &str) -> String {
+		if self.color {
+			format!("{color}{text}{RESET}")
+		} else {
+			text.into()
+		}
+	}
+}
+
+enum Hunk<'a> {
+	Same(Vec<&'a str>),
+	Changed { expected: Vec<&'a str>, found: Vec<&'a str> },
+	Collapsed(usize),
+}
+
+/// Groups the line-level edit script of `expected` and `found` into runs of identical and changed
+/// lines.
+fn hunks<'a>(expected: &'a str, found: &'a str) -> Vec<Hunk<'a>> {
+	let expected = expected.lines().collect::<Vec<_>>();
+	let found = found.lines().collect::<Vec<_>>();
+
+	let mut hunks = Vec::new();
+	let (mut i, mut j) = (0, 0);
+
+	for edit in lcs_edits(&expected, &found) {
+		match (edit, hunks.last_mut()) {
+			(Edit::Keep, Some(Hunk::Same(lines))) => lines.push(expected[i]),
+			(Edit::Keep, _) => hunks.push(Hunk::Same(vec![expected[i]])),
+			(Edit::Remove, Some(Hunk::Changed { expected: removed, .. })) => {
+				removed.push(expected[i]);
+			},
+			(Edit::Remove, _) => {
+				hunks.push(Hunk::Changed { expected: vec![expected[i]], found: vec![] });
+			},
+			(Edit::Insert, Some(Hunk::Changed { found: inserted, .. })) => {
+				inserted.push(found[j]);
+			},
+			(Edit::Insert, _) => {
+				hunks.push(Hunk::Changed { expected: vec![], found: vec![found[j]] });
+			},
+		}
+
+		match edit {
+			Edit::Keep => {
+				i += 1;
+				j += 1;
+			},
+			Edit::Remove => i += 1,
+			Edit::Insert => j += 1,
+		}
+	}
+
+	hunks
+}
+
+fn push_line(out: &mut String, line: &str) {
+	if !out.is_empty() {
+		out.push('\n');
+	}
+
+	// writing to a String can't fail
+	let _ = write!(out, "{line}");
+}
+
+#[cfg(test)]
+mod test_render {
+	use super::{Layout, Renderer};
+
+	const RENDERER: Renderer = Renderer { color: false, layout: Layout::Unified, context: 1 };
+
+	#[test]
+	fn test_unified() {
+		assert_eq!(RENDERER.render_diff("a\nb\nc", "a\nB\nc"), "  a\n- b\n+ B\n  c");
+		assert_eq!(RENDERER.render_diff("", "a\nb"), "+ a\n+ b");
+		assert_eq!(RENDERER.render_diff("a", ""), "- a");
+	}
+
+	#[test]
+	fn test_collapse() {
+		let expected = "0\n1\n2\n3\n4\n5\n6\n7\n8\n9";
+		let found = "0\n1\n2\n3\n4\nfive\n6\n7\n8\n9";
+
+		assert_eq!(
+			RENDERER.render_diff(expected, found),
+			"  ... 4 identical lines ...\n  4\n- 5\n+ five\n  6\n  ... 3 identical lines ..."
+		);
+	}
+
+	#[test]
+	fn test_side_by_side() {
+		let renderer = Renderer { layout: Layout::SideBySide, ..RENDERER };
+
+		assert_eq!(
+			renderer.render_diff("a\nbb\nc", "a\nB\nd\nc"),
+			"  a  |   a\n- bb | + B\n     | + d\n  c  |   c"
+		);
+	}
+
+	#[test]
+	fn test_color() {
+		let renderer = Renderer { color: true, ..RENDERER };
+
+		assert_eq!(renderer.render_diff("a", "b"), "\x1b[31m- a\x1b[0m\n\x1b[32m+ b\x1b[0m");
+	}
+}
@@ -0,0 +1,113 @@
+//! Checking the storages of every pallet in a runtime.
+//!
+//! `construct_runtime!` generates `AllPalletsWithSystem`, a tuple of all of the pallets in the
+//! runtime. [`AllPalletStorages`] turns this tuple into a list of [`PalletStorage`]s that can be
+//! passed to [`do_action`](crate::do_action), asserting that every pallet that isn't explicitly
+//! checked is unchanged:
+//!
+//! ```rust,ignore
+//! do_action::<<AllPalletsWithSystem as AllPalletStorages>::Storages, _, _>(|| { ... })
+//!     .check_storage::<PalletStorage<System>, _>(any_change())
+//!     .check_storage::<PalletStorage<Balances>, _>(any_change())
+//!     .check_storage::<PalletStorage<Example>, _>(any_change())
+//!     .assert_storage_changes();
+//! ```
+//!
+//! The [`PalletStorage`] of a pallet can also be replaced by the individual storages of that
+//! pallet with [`expand_pallet`](crate::AssertableDiffableStorageAction::expand_pallet), to check
+//! those storages with their own typed expectations:
+//!
+//! ```rust,ignore
+//! do_action::<<AllPalletsWithSystem as AllPalletStorages>::Storages, _, _>(|| { ... })
+//!     .expand_pallet::<Example, pallet_example::PalletStorages<Runtime>, _>()
+//!     .check_storage::<pallet_example::Something<Runtime>, _>(OptionDiff::WasNoneNowSome(42))
+//!     .assert_storage_changes();
+//! ```
+
+use core::marker::PhantomData;
+
+use frame_support::{storage::PrefixIterator, traits::PalletInfoAccess};
+use sp_io::hashing::twox_128;
+use sp_std::{collections::btree_map::BTreeMap, vec, vec::Vec};
+
+use crate::{
+	check_storage::{written_entries, CheckStorage, OpaqueKey, OpaqueValue, StorageId},
+	hlist::PalletStorageHList,
+	recorder::RawChange,
+};
+
+/// Checks all of the storage of the pallet `P` at once, by it's raw keys and values. This works for
+/// any pallet, including those without a generated list of their storages.
+///
+/// The entries are keyed by their [`OpaqueKey`] relative to the pallet prefix, i.e.
+/// `twox128(storage) ++ hashed keys`. Expectations for the pallet are supplied as for any other
+/// storage, usually with a matcher such as [`any_change`](crate::expectation::any_change).
+pub struct PalletStorage<P>(PhantomData<P>);
+
+impl<P: PalletInfoAccess> CheckStorage for PalletStorage<P> {
+	type Value = BTreeMap<OpaqueKey, OpaqueValue>;
+
+	fn name() -> StorageId {
+		StorageId { pallet: P::name(), storage: "*" }
+	}
+
+	fn current_value() -> Self::Value {
+		let prefix = twox_128(P::name().as_bytes()).to_vec();
+
+		PrefixIterator::<(OpaqueKey, OpaqueValue)>::new(prefix.clone(), prefix, |key, value| {
+			Ok((OpaqueKey(key.to_vec()), OpaqueValue(value.to_vec())))
+		})
+		.collect()
+	}
+
+	fn prefixes() -> Vec<Vec<u8>> {
+		vec![twox_128(P::name().as_bytes()).to_vec()]
+	}
+
+	fn recorded_values(changes: &[RawChange]) -> Option<(Self::Value, Self::Value)> {
+		Some(written_entries(changes, &twox_128(P::name().as_bytes()), |key, value| {
+			Some((OpaqueKey(key.to_vec()), OpaqueValue(value.to_vec())))
+		}))
+	}
+}
+
+/// A tuple of pallets, such as the `AllPalletsWithSystem` generated by `construct_runtime!`.
+pub trait AllPalletStorages {
+	/// A [`PalletStorage`] for each of the pallets, in the same order.
+	type Storages: PalletStorageHList;
+
+	/// The names of the pallets, in the same order.
+	fn names() -> Vec<&'static str>;
+}
+
+impl AllPalletStorages for () {
+	type Storages = ();
+
+	fn names() -> Vec<&'static str> {
+		Vec::new()
+	}
+}
+
+macro_rules! impl_all_pallet_storages {
+	($first:ident $(, $rest:ident)*) => {
+		impl<$first: PalletInfoAccess, $($rest: PalletInfoAccess),*> AllPalletStorages
+			for ($first, $($rest,)*)
+		{
+			type Storages = (PalletStorage<$first>, <($($rest,)*) as AllPalletStorages>::Storages);
+
+			fn names() -> Vec<&'static str> {
+				let mut names = <($($rest,)*) as AllPalletStorages>::names();
+				names.insert(0, $first::name());
+				names
+			}
+		}
+
+		impl_all_pallet_storages!($($rest),*);
+	};
+	() => {};
+}
+
+impl_all_pallet_storages!(
+	A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z, A1, B1, C1, D1,
+	E1, F1
+);
@@ -0,0 +1,111 @@
+//! Detection of writes to keys that aren't part of any of the declared storages. See
+//! [`AssertableDiffableStorageAction::strict`](crate::AssertableDiffableStorageAction::strict).
+
+use sp_io::hashing::twox_128;
+use sp_std::{collections::btree_map::BTreeMap, vec::Vec};
+
+use crate::{check_storage::StorageId, error::UndeclaredWrite, recorder::RawChange};
+
+/// Configuration of strict mode.
+#[derive(Debug, Default)]
+pub(crate) struct Strict {
+	/// Pallets whose storages may be written to without being declared.
+	pub(crate) ignored_pallets: Vec<&'static str>,
+	/// Pallets that undeclared writes may be attributed to, in addition to the pallets of the
+	/// declared storages.
+	pub(crate) known_pallets: Vec<&'static str>,
+}
+
+/// All of the keys and values under a prefix at a point in time.
+pub(crate) struct Snapshot(BTreeMap<Vec<u8>, Vec<u8>>);
+
+impl Snapshot {
+	/// Takes a snapshot of the entire storage.
+	pub(crate) fn take() -> Self {
+		Self::take_under(&[])
+	}
+
+	/// Takes a snapshot of all of the keys under `prefix`.
+	pub(crate) fn take_under(prefix: &[u8]) -> Self {
+		let mut storage = BTreeMap::new();
+
+		if let Some(value) = sp_io::storage::get(prefix) {
+			storage.insert(prefix.to_vec(), value.to_vec());
+		}
+
+		let mut previous_key = prefix.to_vec();
+
+		while let Some(key) = sp_io::storage::next_key(&previous_key) {
+			if !key.starts_with(prefix) {
+				break;
+			}
+
+			if let Some(value) = sp_io::storage::get(&key) {
+				storage.insert(key.clone(), value.to_vec());
+			}
+
+			previous_key = key;
+		}
+
+		Self(storage)
+	}
+
+	/// The keys that changed between `self` and `after`, ordered by key.
+	pub(crate) fn changes(self, after: Self) -> Vec<RawChange> {
+		let mut before = self.0;
+		let mut changes = Vec::new();
+
+		for (key, value) in after.0 {
+			let value_before = before.remove(&key);
+
+			if value_before.as_ref() != Some(&value) {
+				changes.push(RawChange {
+					key,
+					child_trie: None,
+					before: value_before,
+					after: Some(value),
+				});
+			}
+		}
+
+		// anything left was removed
+		changes.extend(before.into_iter().map(|(key, value)| RawChange {
+			key,
+			child_trie: None,
+			before: Some(value),
+			after: None,
+		}));
+		changes.sort_by(|a, b| a.key.cmp(&b.key));
+
+		changes
+	}
+}
+
+/// The `changes` that aren't under any of the `declared` prefixes, excluding the keys of the
+/// `strict.ignored_pallets`. Each write is attributed to the pallet it's under, out of the pallets
+/// of the `declared` storages and the `strict.known_pallets`.
+pub(crate) fn undeclared_writes(
+	changes: Vec<RawChange>,
+	declared: &[(StorageId, Vec<u8>)],
+	strict: &Strict,
+) -> Vec<UndeclaredWrite> {
+	let is_in_pallet = |key: &[u8], pallet: &str| key.starts_with(&twox_128(pallet.as_bytes()));
+
+	changes
+		.into_iter()
+		.filter(|change| !declared.iter().any(|(_, prefix)| change.key_under(prefix).is_some()))
+		.filter(|change| {
+			!strict.ignored_pallets.iter().any(|pallet| is_in_pallet(&change.key, pallet))
+		})
+		.map(|RawChange { key, before, after, .. }| UndeclaredWrite {
+			pallet: declared
+				.iter()
+				.map(|(storage, _)| storage.pallet)
+				.chain(strict.known_pallets.iter().copied())
+				.find(|pallet| is_in_pallet(&key, pallet)),
+			key,
+			before,
+			after,
+		})
+		.collect()
+}
@@ -1,3 +1,4 @@
 fn main() {
 	test_builder::aggregate_tests("testing");
+	test_builder::aggregate_storages();
 }
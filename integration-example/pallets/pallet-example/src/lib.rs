@@ -15,6 +15,9 @@ pub mod testing;
 #[cfg(feature = "testing")]
 include!(concat!(env!("OUT_DIR"), "/testing.rs"));
 
+#[cfg(feature = "testing")]
+include!(concat!(env!("OUT_DIR"), "/storages.rs"));
+
 #[frame_support::pallet]
 pub mod pallet {
 	use frame_support::pallet_prelude::*;
@@ -39,6 +42,30 @@ pub mod pallet {
 	#[pallet::getter(fn something)]
 	pub type Something<T> = StorageValue<_, u32>;
 
+	#[pallet::storage]
+	pub type Positions<T> = StorageNMap<
+		_,
+		(NMapKey<Blake2_128Concat, u32>, NMapKey<Twox64Concat, u32>),
+		u32,
+		OptionQuery,
+	>;
+
+	#[pallet::storage]
+	pub type Accounts<T> = CountedStorageMap<_, Blake2_128Concat, u32, u32>;
+
+	#[pallet::storage]
+	pub type Hashed<T> = StorageMap<_, Blake2_128, u32, u32>;
+
+	#[pallet::storage]
+	pub type Entries<T> = StorageMap<_, Twox64Concat, u32, u32>;
+
+	#[pallet::storage]
+	pub type Approvals<T> = StorageDoubleMap<_, Blake2_128Concat, u32, Twox64Concat, u32, u32>;
+
+	#[pallet::storage]
+	#[pallet::unbounded]
+	pub type History<T> = StorageValue<_, Vec<u32>, ValueQuery>;
+
 	// #[pallet::storage]
 	// pub type Bounded<T> =
 	// 	StorageMap<_, Blake2_128Concat, u32, u32, OptionQuery, GetDefault, >;
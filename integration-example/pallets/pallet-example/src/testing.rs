@@ -1,15 +1,39 @@
-use change_set::{diff::OptionDiff, do_action};
+use core::fmt::Debug;
+use std::sync::{Arc, Mutex};
+
+use change_set::{
+	check_storage::{
+		AsDelta, AsOption, CheckStorage, Flat, Opaque, OpaqueKey, OpaqueValue, StorageId,
+	},
+	child::{ChildStorage, ChildTrie, RawChildStorage},
+	diff::{Diffable, MapValueDiff, OptionDiff, VecDiff},
+	do_action,
+	error::StorageAssertionErrorKind,
+	expectation::matching,
+	recorder::execute_with_recording,
+};
 use frame_support::{
-	assert_noop, assert_ok,
+	assert_ok,
+	sp_io::TestExternalities,
 	sp_runtime::{
 		traits::{GetNodeBlockType, GetRuntimeBlockType},
-		AccountId32,
+		AccountId32, DispatchError,
 	},
-	traits::{Hooks, OriginTrait},
+	sp_std::collections::btree_map::BTreeMap,
+	storage::{
+		child::{self, ChildInfo},
+		unhashed, with_storage_layer, StoragePrefixedMap,
+	},
+	traits::{Hooks, OriginTrait, PalletInfoAccess},
+	Blake2_128, StorageHasher,
 };
 use frame_system::{pallet_prelude::OriginFor, Config as SystemConfig, Pallet as System};
+use parity_scale_codec::Encode;
 
-use crate::{Config, Event, Pallet, Something};
+use crate::{
+	Accounts, Approvals, Config, Entries, Event, Hashed, History, Pallet, PalletStorages,
+	Positions, Something,
+};
 
 const ALICE: AccountId32 = AccountId32::new([0; 32]);
 
@@ -52,26 +76,529 @@ where
 	System::<T>::set_block_number(1.into());
 
 	// System::on_initialize
-	// Dispatch a signed extrinsic.
-	do_action::<(Something<T>, ()), _, _>(|| {
+	// Dispatch a signed extrinsic, and assert that the correct event was deposited.
+	do_action::<PalletStorages<T>, _, _>(|| {
 		Pallet::<T>::do_something(OriginFor::<T>::signed(ALICE.into()), 42).unwrap();
-		System::<T>::assert_last_event(
-			Event::SomethingStored { something: 42, who: ALICE.into() }.into(),
-		);
 	})
 	.check_storage::<Something<T>, _>(OptionDiff::WasNoneNowSome(42))
+	.check_events::<T>([Event::<T>::SomethingStored { something: 42, who: ALICE.into() }])
 	.assert_storage_changes();
-
-	// Assert that the correct event was deposited
 }
 
 pub fn correct_error_for_none_value<T>()
 where
 	T: ExamplePalletRuntimeBounds,
 {
-	// Ensure the expected error is thrown when no value is present.
-	assert_noop!(
-		Pallet::<T>::cause_error(OriginFor::<T>::signed(ALICE.into())),
-		crate::Error::<T>::NoneValue
+	// Ensure the expected error is thrown when no value is present, without changing any of the
+	// storages of the pallet, or writing to any other storage.
+	do_action::<PalletStorages<T>, _, _>(|| {
+		Pallet::<T>::cause_error(OriginFor::<T>::signed(ALICE.into()))
+	})
+	.expect_err(crate::Error::<T>::NoneValue)
+	.strict()
+	.assert_storage_changes();
+}
+
+pub fn nmap_changes_are_keyed_by_the_key_tuple<T>()
+where
+	T: ExamplePalletRuntimeBounds,
+{
+	Positions::<T>::insert((1_u32, 2_u32), 10);
+
+	do_action::<PalletStorages<T>, _, _>(|| {
+		Positions::<T>::insert((1_u32, 2_u32), 11);
+		Positions::<T>::insert((3_u32, 4_u32), 20);
+	})
+	.check_storage::<Positions<T>, _>(BTreeMap::from([
+		((1_u32, 2_u32), MapValueDiff::Changed(11_u32)),
+		((3, 4), MapValueDiff::Added(20)),
+	]))
+	.assert_storage_changes();
+}
+
+pub fn counter_is_consistent_with_inserted_and_removed_entries<T>()
+where
+	T: ExamplePalletRuntimeBounds,
+{
+	Accounts::<T>::insert(1, 10);
+
+	do_action::<PalletStorages<T>, _, _>(|| {
+		Accounts::<T>::remove(1);
+		Accounts::<T>::insert(2, 20);
+		Accounts::<T>::insert(3, 30);
+	})
+	.check_storage::<Accounts<T>, _>(BTreeMap::from([
+		(1_u32, MapValueDiff::Missing),
+		(2, MapValueDiff::Added(20_u32)),
+		(3, MapValueDiff::Added(30)),
+	]))
+	.assert_storage_changes();
+}
+
+pub fn counter_drift_is_reported<T>()
+where
+	T: ExamplePalletRuntimeBounds,
+{
+	let report = do_action::<PalletStorages<T>, _, _>(|| {
+		// inserts an entry without incrementing the counter
+		unhashed::put(&Accounts::<T>::hashed_key_for(1), &10_u32);
+	})
+	.check_storage::<Accounts<T>, _>(BTreeMap::from([(1_u32, MapValueDiff::Added(10_u32))]))
+	.try_assert_storage_changes()
+	.unwrap_err();
+
+	assert_eq!(report.errors.len(), 1);
+	assert_eq!(report.errors[0].storage, Accounts::<T>::name());
+	assert_eq!(report.errors[0].kind, StorageAssertionErrorKind::CounterDrift);
+}
+
+pub fn counter_drift_from_before_the_action_is_not_reported<T>()
+where
+	T: ExamplePalletRuntimeBounds,
+{
+	unhashed::put(&Accounts::<T>::hashed_key_for(1), &10_u32);
+
+	do_action::<PalletStorages<T>, _, _>(|| Accounts::<T>::insert(2, 20))
+		.check_storage::<Accounts<T>, _>(BTreeMap::from([(2_u32, MapValueDiff::Added(20_u32))]))
+		.assert_storage_changes();
+}
+
+pub fn keyed_checks_report_writes_to_other_keys<T>()
+where
+	T: ExamplePalletRuntimeBounds,
+{
+	// the other entries are only checked from the recorded writes
+	let report = execute_with_recording(&mut TestExternalities::default().ext(), || {
+		Accounts::<T>::insert(1, 10);
+
+		do_action::<PalletStorages<T>, _, _>(|| {
+			Accounts::<T>::insert(1, 11);
+			Accounts::<T>::insert(2, 20);
+		})
+		.check_storage_keys::<Accounts<T>, _>(
+			[1],
+			BTreeMap::from([(1_u32, MapValueDiff::Changed(11_u32))]),
+		)
+		.try_assert_storage_changes()
+		.unwrap_err()
+	});
+
+	// the counter changed by as much as the entries did
+	assert!(report.undeclared_writes.is_empty());
+	assert_eq!(report.errors.len(), 1);
+	assert_eq!(report.errors[0].storage, Accounts::<T>::name());
+	assert_eq!(report.errors[0].kind, StorageAssertionErrorKind::UnexpectedChange);
+}
+
+pub fn keyed_checks_report_counter_drift<T>()
+where
+	T: ExamplePalletRuntimeBounds,
+{
+	let report = execute_with_recording(&mut TestExternalities::default().ext(), || {
+		do_action::<PalletStorages<T>, _, _>(|| {
+			// inserts an entry without incrementing the counter
+			unhashed::put(&Accounts::<T>::hashed_key_for(1), &10_u32);
+		})
+		.check_storage_keys::<Accounts<T>, _>(
+			[1],
+			BTreeMap::from([(1_u32, MapValueDiff::Added(10_u32))]),
+		)
+		.try_assert_storage_changes()
+		.unwrap_err()
+	});
+
+	assert!(report.undeclared_writes.is_empty());
+	assert_eq!(report.errors.len(), 1);
+	assert_eq!(report.errors[0].storage, Accounts::<T>::name());
+	assert_eq!(report.errors[0].kind, StorageAssertionErrorKind::CounterDrift);
+}
+
+pub fn non_reversible_maps_are_keyed_by_their_raw_keys<T>()
+where
+	T: ExamplePalletRuntimeBounds,
+{
+	let key = Opaque::<Hashed<T>>::key(1_u32);
+
+	assert_eq!(key, OpaqueKey(Blake2_128::hash(&1_u32.encode()).to_vec()));
+
+	do_action::<PalletStorages<T>, _, _>(|| {
+		Hashed::<T>::insert(1, 10);
+	})
+	.check_storage::<Opaque<Hashed<T>>, _>(BTreeMap::from([(key, MapValueDiff::Added(10_u32))]))
+	.assert_storage_changes();
+}
+
+pub fn strict_mode_reports_undeclared_writes<T>()
+where
+	T: ExamplePalletRuntimeBounds,
+{
+	let report = do_action::<(Something<T>, ()), _, _>(|| {
+		Something::<T>::put(1);
+		Positions::<T>::insert((1_u32, 2_u32), 3);
+	})
+	.check_storage::<Something<T>, _>(OptionDiff::WasNoneNowSome(1))
+	.strict()
+	.try_assert_storage_changes()
+	.unwrap_err();
+
+	assert!(report.errors.is_empty());
+	assert_eq!(report.undeclared_writes.len(), 1);
+	assert_eq!(report.undeclared_writes[0].key, Positions::<T>::hashed_key_for((1_u32, 2_u32)));
+	assert_eq!(report.undeclared_writes[0].pallet, Some(<Pallet<T> as PalletInfoAccess>::name()));
+}
+
+pub fn strict_mode_allows_writes_to_ignored_pallets<T>()
+where
+	T: ExamplePalletRuntimeBounds,
+{
+	do_action::<(Something<T>, ()), _, _>(|| {
+		Something::<T>::put(1);
+		Positions::<T>::insert((1_u32, 2_u32), 3);
+	})
+	.check_storage::<Something<T>, _>(OptionDiff::WasNoneNowSome(1))
+	.ignore_pallet(<Pallet<T> as PalletInfoAccess>::name())
+	.assert_storage_changes();
+}
+
+pub fn undeclared_writes_are_attributed_to_known_pallets<T>()
+where
+	T: ExamplePalletRuntimeBounds,
+{
+	let report = do_action::<(Something<T>, ()), _, _>(|| System::<T>::set_block_number(5.into()))
+		.strict()
+		.try_assert_storage_changes()
+		.unwrap_err();
+
+	assert_eq!(report.undeclared_writes.len(), 1);
+	assert_eq!(report.undeclared_writes[0].pallet, None);
+
+	let report = do_action::<(Something<T>, ()), _, _>(|| System::<T>::set_block_number(6.into()))
+		.known_pallets::<(System<T>, Pallet<T>)>()
+		.try_assert_storage_changes()
+		.unwrap_err();
+
+	assert_eq!(report.undeclared_writes.len(), 1);
+	assert_eq!(report.undeclared_writes[0].pallet, Some(<System<T> as PalletInfoAccess>::name()));
+}
+
+pub fn killed_and_default_values_differ_as_options<T>()
+where
+	T: ExamplePalletRuntimeBounds,
+{
+	History::<T>::put(vec![1_u32]);
+
+	do_action::<(AsOption<History<T>>, ()), _, _>(History::<T>::kill)
+		.check_storage::<AsOption<History<T>>, _>(OptionDiff::WasSomeNowNone)
+		.assert_storage_changes();
+
+	History::<T>::put(vec![1_u32]);
+
+	do_action::<(AsOption<History<T>>, ()), _, _>(|| History::<T>::put(Vec::<u32>::new()))
+		.check_storage::<AsOption<History<T>>, _>(OptionDiff::ValueChanged(vec![
+			VecDiff::Removed { index: 0 },
+		]))
+		.assert_storage_changes();
+
+	// read through the `ValueQuery`, both are the same change to the default value
+	History::<T>::put(vec![1_u32]);
+
+	do_action::<(History<T>, ()), _, _>(History::<T>::kill)
+		.check_storage::<History<T>, _>(vec![VecDiff::Removed { index: 0 }])
+		.assert_storage_changes();
+}
+
+/// Runs `action` twice on a fresh storage set up by `setup`, once with it's writes
+/// [recorded](change_set::recorder) and once without, and asserts that the storage `S` is found to
+/// have changed the same either way.
+fn assert_recorded_diff_is_snapshot_diff<S>(setup: impl Fn(), action: impl Fn())
+where
+	S: CheckStorage,
+	<S::Value as Diffable>::ChangeSet: Debug + Send + Sync + 'static,
+{
+	let found_change = || {
+		let found = Arc::new(Mutex::new(None));
+		let captured = found.clone();
+
+		setup();
+
+		do_action::<(S, ()), _, _>(&action)
+			.check_storage::<S, _>(matching("any change", move |change| {
+				*captured.lock().unwrap() = Some(format!("{change:?}"));
+				true
+			}))
+			.assert_storage_changes();
+
+		let found = found.lock().unwrap().take();
+		found
+	};
+
+	let snapshot_diff = TestExternalities::default().execute_with(found_change);
+	let recorded_diff =
+		execute_with_recording(&mut TestExternalities::default().ext(), found_change);
+
+	assert_eq!(recorded_diff, snapshot_diff);
+}
+
+/// Runs `check` twice on a fresh storage, once with it's writes [recorded](change_set::recorder)
+/// and once without.
+fn in_both_modes(check: impl Fn() + Copy) {
+	TestExternalities::default().execute_with(check);
+	execute_with_recording(&mut TestExternalities::default().ext(), check);
+}
+
+pub fn recorded_diff_of_placed_values<T>()
+where
+	T: ExamplePalletRuntimeBounds,
+{
+	assert_recorded_diff_is_snapshot_diff::<Something<T>>(
+		|| Something::<T>::put(1_u32),
+		|| Something::<T>::put(2_u32),
+	);
+	assert_recorded_diff_is_snapshot_diff::<AsDelta<Something<T>>>(
+		|| Something::<T>::put(5_u32),
+		|| Something::<T>::put(2_u32),
+	);
+}
+
+pub fn recorded_diff_of_nested_double_map<T>()
+where
+	T: ExamplePalletRuntimeBounds,
+{
+	assert_recorded_diff_is_snapshot_diff::<Approvals<T>>(
+		|| {
+			Approvals::<T>::insert(1_u32, 1_u32, 10_u32);
+			Approvals::<T>::insert(1_u32, 2_u32, 20_u32);
+			Approvals::<T>::insert(2_u32, 1_u32, 10_u32);
+			Approvals::<T>::insert(3_u32, 1_u32, 10_u32);
+			Approvals::<T>::insert(5_u32, 1_u32, 10_u32);
+			Approvals::<T>::insert(5_u32, 2_u32, 20_u32);
+		},
+		|| {
+			// only removed from a first key with other entries
+			Approvals::<T>::remove(1_u32, 1_u32);
+			// only inserted into a first key with other entries
+			Approvals::<T>::insert(3_u32, 2_u32, 20_u32);
+			// the last entry of a first key
+			Approvals::<T>::remove(2_u32, 1_u32);
+			// the first entry of a first key
+			Approvals::<T>::insert(4_u32, 1_u32, 10_u32);
+			// changed in place
+			Approvals::<T>::insert(5_u32, 1_u32, 11_u32);
+		},
+	);
+}
+
+pub fn first_keys_of_nested_double_maps_are_always_changed<T>()
+where
+	T: ExamplePalletRuntimeBounds,
+{
+	in_both_modes(|| {
+		Approvals::<T>::insert(1_u32, 1_u32, 10_u32);
+
+		do_action::<(Approvals<T>, ()), _, _>(|| {
+			Approvals::<T>::remove(1_u32, 1_u32);
+			Approvals::<T>::insert(2_u32, 1_u32, 10_u32);
+		})
+		.check_storage::<Approvals<T>, _>(BTreeMap::from([
+			(1_u32, MapValueDiff::Changed(BTreeMap::from([(1_u32, MapValueDiff::Missing)]))),
+			(2_u32, MapValueDiff::Changed(BTreeMap::from([(1_u32, MapValueDiff::Added(10_u32))]))),
+		]))
+		.assert_storage_changes();
+	});
+}
+
+pub fn flat_double_maps_are_keyed_by_both_keys<T>()
+where
+	T: ExamplePalletRuntimeBounds,
+{
+	in_both_modes(|| {
+		Approvals::<T>::insert(1_u32, 1_u32, 10_u32);
+
+		do_action::<(Flat<Approvals<T>>, ()), _, _>(|| {
+			Approvals::<T>::remove(1_u32, 1_u32);
+			Approvals::<T>::insert(1_u32, 2_u32, 20_u32);
+			Approvals::<T>::insert(2_u32, 1_u32, 10_u32);
+		})
+		.check_storage::<Flat<Approvals<T>>, _>(BTreeMap::from([
+			((1_u32, 1_u32), MapValueDiff::Missing),
+			((1_u32, 2_u32), MapValueDiff::Added(20_u32)),
+			((2_u32, 1_u32), MapValueDiff::Added(10_u32)),
+		]))
+		.assert_storage_changes();
+	});
+}
+
+pub fn recorded_diff_of_appended_values<T>()
+where
+	T: ExamplePalletRuntimeBounds,
+{
+	assert_recorded_diff_is_snapshot_diff::<History<T>>(
+		|| History::<T>::append(1_u32),
+		|| {
+			History::<T>::append(2_u32);
+			History::<T>::append(3_u32);
+		},
+	);
+}
+
+pub fn recorded_diff_of_cleared_prefix<T>()
+where
+	T: ExamplePalletRuntimeBounds,
+{
+	assert_recorded_diff_is_snapshot_diff::<Entries<T>>(
+		|| (1..=5_u32).for_each(|i| Entries::<T>::insert(i, i * 10)),
+		|| {
+			let _ = unhashed::clear_prefix(&Entries::<T>::final_prefix(), Some(2), None);
+		},
 	);
 }
+
+pub fn recorded_diff_of_rolled_back_storage_layer<T>()
+where
+	T: ExamplePalletRuntimeBounds,
+{
+	assert_recorded_diff_is_snapshot_diff::<Entries<T>>(
+		|| Entries::<T>::insert(1_u32, 10_u32),
+		|| {
+			Entries::<T>::insert(2_u32, 20_u32);
+
+			let _ = with_storage_layer::<(), DispatchError, _>(|| {
+				Entries::<T>::insert(1_u32, 11_u32);
+				Entries::<T>::insert(3_u32, 30_u32);
+
+				Err(DispatchError::Other("rolled back"))
+			});
+		},
+	);
+}
+
+pub fn corrupt_entries_are_reported_as_undecodable<T>()
+where
+	T: ExamplePalletRuntimeBounds,
+{
+	let key = Entries::<T>::hashed_key_for(1_u32);
+
+	in_both_modes(|| {
+		// a `u32` takes 4 bytes to encode
+		let report = do_action::<PalletStorages<T>, _, _>(|| unhashed::put_raw(&key, &[0xff]))
+			.try_assert_storage_changes()
+			.unwrap_err();
+
+		assert_eq!(report.errors.len(), 1);
+		assert_eq!(report.errors[0].storage, Entries::<T>::name());
+		assert_eq!(report.errors[0].kind, StorageAssertionErrorKind::Undecodable);
+		assert!(format!("{:?}", report.errors[0].found).contains("value: 0xff"));
+	});
+}
+
+/// A child trie written to directly by the tests.
+struct Contributions;
+
+impl ChildTrie for Contributions {
+	fn name() -> StorageId {
+		StorageId { pallet: "Example", storage: "Contributions" }
+	}
+
+	fn child_info() -> ChildInfo {
+		ChildInfo::new_default(b"contributions")
+	}
+}
+
+pub fn recorded_diff_of_child_writes<T>()
+where
+	T: ExamplePalletRuntimeBounds,
+{
+	let child_info = Contributions::child_info();
+
+	assert_recorded_diff_is_snapshot_diff::<ChildStorage<Contributions, u32, u32>>(
+		|| {
+			child::put(&child_info, &1_u32.encode(), &10_u32);
+			child::put(&child_info, &2_u32.encode(), &20_u32);
+		},
+		|| {
+			child::put(&child_info, &2_u32.encode(), &21_u32);
+			child::put(&child_info, &3_u32.encode(), &30_u32);
+			child::kill(&child_info, &1_u32.encode());
+		},
+	);
+}
+
+pub fn child_storages_decode_their_entries<T>()
+where
+	T: ExamplePalletRuntimeBounds,
+{
+	let child_info = Contributions::child_info();
+
+	in_both_modes(|| {
+		child::put(&child_info, &1_u32.encode(), &10_u32);
+
+		do_action::<(ChildStorage<Contributions, u32, u32>, ()), _, _>(|| {
+			child::put(&child_info, &2_u32.encode(), &20_u32);
+			child::kill(&child_info, &1_u32.encode());
+		})
+		.check_storage::<ChildStorage<Contributions, u32, u32>, _>(BTreeMap::from([
+			(1_u32, MapValueDiff::Missing),
+			(2_u32, MapValueDiff::Added(20_u32)),
+		]))
+		.assert_storage_changes();
+	});
+
+	in_both_modes(|| {
+		// a `u32` takes 4 bytes to encode
+		let report = do_action::<(ChildStorage<Contributions, u32, u32>, ()), _, _>(|| {
+			child::put_raw(&child_info, &1_u32.encode(), &[0xff]);
+		})
+		.try_assert_storage_changes()
+		.unwrap_err();
+
+		assert_eq!(report.errors.len(), 1);
+		assert_eq!(report.errors[0].storage, Contributions::name());
+		assert_eq!(report.errors[0].kind, StorageAssertionErrorKind::Undecodable);
+	});
+}
+
+pub fn raw_child_storages_are_keyed_by_their_raw_keys<T>()
+where
+	T: ExamplePalletRuntimeBounds,
+{
+	in_both_modes(|| {
+		do_action::<(RawChildStorage<Contributions>, ()), _, _>(|| {
+			child::put_raw(&Contributions::child_info(), b"alice", &[0xff]);
+		})
+		.check_storage::<RawChildStorage<Contributions>, _>(BTreeMap::from([(
+			OpaqueKey(b"alice".to_vec()),
+			MapValueDiff::Added(OpaqueValue(vec![0xff])),
+		)]))
+		.assert_storage_changes();
+	});
+}
+
+/// A child trie whose storage key starts with the storage key of [`Contributions`].
+struct ArchivedContributions;
+
+impl ChildTrie for ArchivedContributions {
+	fn name() -> StorageId {
+		StorageId { pallet: "Example", storage: "ArchivedContributions" }
+	}
+
+	fn child_info() -> ChildInfo {
+		ChildInfo::new_default(b"contributions-archive")
+	}
+}
+
+pub fn child_tries_are_matched_exactly<T>()
+where
+	T: ExamplePalletRuntimeBounds,
+{
+	type Current = RawChildStorage<Contributions>;
+	type Archived = ChildStorage<ArchivedContributions, u32, u32>;
+
+	in_both_modes(|| {
+		do_action::<(Current, (Archived, ())), _, _>(|| {
+			child::put(&ArchivedContributions::child_info(), &1_u32.encode(), &10_u32);
+		})
+		.check_storage::<Archived, _>(BTreeMap::from([(1_u32, MapValueDiff::Added(10_u32))]))
+		.strict()
+		.assert_storage_changes();
+	});
+}
@@ -1 +1,50 @@
+use change_set::{
+	check_storage::CheckStorage, diff::OptionDiff, do_action, expectation::any_change,
+	runtime::PalletStorage,
+};
+use frame_support::BasicExternalities;
+use sp_runtime::AccountId32;
+use substrate_pallet_testing_framework::RuntimeStorages;
+
+use crate::{AllPalletsWithSystem, Balances, Block, ExampleModule, Runtime, RuntimeOrigin};
+
 ::pallet_example::tests! { mod pallet_example<crate::Runtime> }
+
+impl substrate_pallet_testing_framework::Runtime for Runtime {
+	type AllPallets = AllPalletsWithSystem;
+	type NodeBlock = crate::opaque::Block;
+	type RuntimeBlock = Block;
+}
+
+const ALICE: AccountId32 = AccountId32::new([0; 32]);
+
+#[test]
+fn runtime_storages_can_be_checked_with_the_typed_storages_of_a_pallet() {
+	BasicExternalities::default().execute_with(|| {
+		do_action::<RuntimeStorages<Runtime>, _, _>(|| {
+			ExampleModule::do_something(RuntimeOrigin::signed(ALICE), 42).unwrap();
+		})
+		.expand_pallet::<ExampleModule, ::pallet_example::PalletStorages<Runtime>, _>()
+		.check_storage::<::pallet_example::Something<Runtime>, _>(OptionDiff::WasNoneNowSome(42))
+		.assert_storage_changes();
+	});
+}
+
+#[test]
+fn unexpanded_pallets_are_checked_by_their_raw_storage() {
+	BasicExternalities::default().execute_with(|| {
+		let report = do_action::<RuntimeStorages<Runtime>, _, _>(|| {
+			ExampleModule::do_something(RuntimeOrigin::signed(ALICE), 42).unwrap();
+		})
+		.expand_pallet::<ExampleModule, ::pallet_example::PalletStorages<Runtime>, _>()
+		.check_storage::<PalletStorage<Balances>, _>(any_change())
+		.try_assert_storage_changes()
+		.unwrap_err();
+
+		// the storages of the example pallet are checked individually, the other pallets as a whole
+		assert_eq!(
+			report.errors.iter().map(|error| error.storage).collect::<Vec<_>>(),
+			[::pallet_example::Something::<Runtime>::name(), PalletStorage::<Balances>::name()]
+		);
+	});
+}
@@ -1,7 +1,24 @@
+use change_set::runtime::AllPalletStorages;
+
 pub trait Runtime {
-	type AllPallets;
+	/// All of the pallets in the runtime, i.e. the `AllPalletsWithSystem` generated by
+	/// `construct_runtime!`.
+	type AllPallets: AllPalletStorages;
 
 	type NodeBlock;
 
 	type RuntimeBlock;
 }
+
+/// Every storage of every pallet in the runtime `R`, checked per pallet with
+/// [`PalletStorage`](change_set::runtime::PalletStorage), or per storage for the pallets that are
+/// [expanded](change_set::AssertableDiffableStorageAction::expand_pallet):
+///
+/// ```rust,ignore
+/// do_action::<RuntimeStorages<Runtime>, _, _>(|| { ... })
+///     .expand_pallet::<Example, pallet_example::PalletStorages<Runtime>, _>()
+///     .check_storage::<pallet_example::Something<Runtime>, _>(OptionDiff::WasNoneNowSome(42))
+///     .check_storage::<PalletStorage<Balances>, _>(any_change())
+///     .assert_storage_changes();
+/// ```
+pub type RuntimeStorages<R> = <<R as Runtime>::AllPallets as AllPalletStorages>::Storages;
@@ -1,6 +1,9 @@
 use std::{env, fmt::Write, fs, iter, path::PathBuf};
 
-use syn::{Generics, Item, ItemFn, ItemMod, ReturnType, Signature, Visibility};
+use syn::{
+	GenericArgument, GenericParam, Generics, Item, ItemFn, ItemMod, ItemType, PathArguments,
+	ReturnType, Signature, Type, Visibility,
+};
 
 pub fn aggregate_tests(tests_module_path: &str) {
 	let dest_path = PathBuf::from(
@@ -49,6 +52,183 @@ pub fn aggregate_tests(tests_module_path: &str) {
 	println!("cargo:rerun-if-changed=src/{tests_module_path}*");
 }
 
+/// Hashers that can't be reversed, meaning that maps using them can only be checked by their raw
+/// keys (with `change_set::check_storage::Opaque`).
+const NON_REVERSIBLE_HASHERS: [&str; 4] = ["Blake2_128", "Blake2_256", "Twox128", "Twox256"];
+
+/// Generates a `PalletStorages<T>` type alias listing every `#[pallet::storage]` of the pallet
+/// defined in `src/lib.rs`, to be used with `change_set::do_action`:
+///
+/// ```rust,ignore
+/// #[cfg(feature = "testing")]
+/// include!(concat!(env!("OUT_DIR"), "/storages.rs"));
+/// ```
+///
+/// Storages that are maps with non-reversible hashers are wrapped in
+/// `change_set::check_storage::Opaque`. If any of the storages are instanceable, the alias is
+/// `PalletStorages<T, I = ()>` instead.
+///
+/// As the alias is included at the crate root, all of the storages must be visible there, i.e. be
+/// at least `pub(super)`. Private storages are reported with a `compile_error!`.
+pub fn aggregate_storages() {
+	let dest_path = PathBuf::from(
+		env::var_os("OUT_DIR").expect("OUT_DIR environment variable should be present"),
+	)
+	.join("storages.rs");
+
+	let Some((pallet_module, items)) = read_pallet_module() else {
+		println!("cargo:rerun-if-changed=src");
+		return;
+	};
+
+	fs::write(dest_path, pallet_storages(&pallet_module, items)).unwrap();
+
+	println!("cargo:rerun-if-changed=src");
+}
+
+/// The `PalletStorages` type alias of the storages in the `items` of the pallet module
+/// `pallet_module`. See [`aggregate_storages`].
+fn pallet_storages(pallet_module: &str, items: Vec<Item>) -> String {
+	let storages = items
+		.into_iter()
+		.filter_map(|item| match item {
+			Item::Type(item_type) if is_pallet_storage(&item_type) => Some(item_type),
+			_ => None,
+		})
+		.collect::<Vec<_>>();
+
+	let private_storages = storages
+		.iter()
+		.filter(|storage| is_private(&storage.vis))
+		.map(|storage| format!("`{}`", storage.ident))
+		.collect::<Vec<_>>();
+
+	if !private_storages.is_empty() {
+		return format!(
+			"::core::compile_error!(\"the storages {} are private to the pallet module `{pallet_module}`, and \
+			 can't be listed in `PalletStorages`; make them at least `pub(super)`\");",
+			private_storages.join(", "),
+		);
+	}
+
+	let is_public = storages.iter().all(|storage| matches!(storage.vis, Visibility::Public(_)));
+	let is_instanceable = storages.iter().any(|storage| storage.generics.params.len() > 1);
+
+	let storages_hlist = if storages.is_empty() {
+		// `T` must still be used by the alias
+		"<::core::marker::PhantomData<T> as ::change_set::NoStorages>::Storages".to_string()
+	} else {
+		storages.iter().rev().fold("()".to_string(), |acc, storage| {
+			let params = storage
+				.generics
+				.params
+				.iter()
+				.filter_map(|param| match param {
+					GenericParam::Type(param) => Some(param.ident.to_string()),
+					_ => None,
+				})
+				.collect::<Vec<_>>();
+
+			let storage_path = if params.is_empty() {
+				format!("crate::{pallet_module}::{}", storage.ident)
+			} else {
+				format!("crate::{pallet_module}::{}<{}>", storage.ident, params.join(", "))
+			};
+
+			if is_non_reversible_map(&storage.ty) {
+				format!("(::change_set::check_storage::Opaque<{storage_path}>, {acc})")
+			} else {
+				format!("({storage_path}, {acc})")
+			}
+		})
+	};
+
+	format!(
+		"
+		/// Every `#[pallet::storage]` of this pallet, in declaration order.
+		{} type PalletStorages<T{}> = {storages_hlist};",
+		if is_public { "pub" } else { "pub(crate)" },
+		if is_instanceable { ", I = ()" } else { "" },
+	)
+}
+
+/// Finds the `#[frame_support::pallet]` module in `src/lib.rs`, returning it's name and items.
+fn read_pallet_module() -> Option<(String, Vec<Item>)> {
+	let ast = read_module_file(vec!["lib".to_string()]).ok()?;
+
+	ast.items.into_iter().find_map(|item| match item {
+		Item::Mod(ItemMod { attrs, ident, content, .. })
+			if attrs.iter().any(|attr| {
+				attr.path.segments.last().map_or(false, |segment| segment.ident == "pallet")
+			}) =>
+		{
+			let items = match content {
+				Some((_, items)) => items,
+				None => read_module_file(vec![ident.to_string()]).ok()?.items,
+			};
+
+			Some((ident.to_string(), items))
+		},
+		_ => None,
+	})
+}
+
+/// Whether or not the type alias has a `#[pallet::storage]` attribute.
+fn is_pallet_storage(item_type: &ItemType) -> bool {
+	item_type.attrs.iter().any(|attr| {
+		let segments = attr.path.segments.iter().map(|s| s.ident.to_string()).collect::<Vec<_>>();
+
+		segments == ["pallet", "storage"]
+	})
+}
+
+/// Whether or not an item with the visibility `vis` in the pallet module can't be named from the
+/// crate root, i.e. it's private or `pub(self)`.
+fn is_private(vis: &Visibility) -> bool {
+	match vis {
+		Visibility::Inherited => true,
+		Visibility::Restricted(restricted) => restricted.path.is_ident("self"),
+		_ => false,
+	}
+}
+
+/// Whether or not the storage type is a map that uses any of the [`NON_REVERSIBLE_HASHERS`].
+fn is_non_reversible_map(ty: &Type) -> bool {
+	fn type_idents(ty: &Type, idents: &mut Vec<String>) {
+		match ty {
+			Type::Path(type_path) => {
+				for segment in &type_path.path.segments {
+					idents.push(segment.ident.to_string());
+
+					if let PathArguments::AngleBracketed(args) = &segment.arguments {
+						for arg in &args.args {
+							if let GenericArgument::Type(ty) = arg {
+								type_idents(ty, idents);
+							}
+						}
+					}
+				}
+			},
+			Type::Tuple(tuple) => tuple.elems.iter().for_each(|ty| type_idents(ty, idents)),
+			_ => {},
+		}
+	}
+
+	let Type::Path(type_path) = ty else {
+		return false;
+	};
+
+	let is_map = type_path.path.segments.last().map_or(false, |segment| {
+		["StorageMap", "StorageDoubleMap", "StorageNMap", "CountedStorageMap"]
+			.contains(&&*segment.ident.to_string())
+	});
+
+	let mut idents = vec![];
+	type_idents(ty, &mut idents);
+
+	is_map && idents.iter().any(|ident| NON_REVERSIBLE_HASHERS.contains(&&**ident))
+}
+
 fn read_module_file(paths: Vec<String>) -> Result<syn::File, String> {
 	let path = format!("src/{}.rs", paths.join("/"));
 
@@ -183,3 +363,147 @@ fn to_output_inner(
 		_ => None,
 	}
 }
+
+#[cfg(test)]
+mod test_pallet_storages {
+	use super::pallet_storages;
+
+	/// The `PalletStorages` alias generated for the pallet module `pallet` with the items `items`,
+	/// with whitespace normalized.
+	fn generate(items: &str) -> String {
+		let items = syn::parse_file(items).unwrap().items;
+
+		normalize(&pallet_storages("pallet", items))
+	}
+
+	fn normalize(s: &str) -> String {
+		s.split_whitespace().collect::<Vec<_>>().join(" ")
+	}
+
+	#[test]
+	fn test_storages_in_declaration_order() {
+		assert_eq!(
+			generate(
+				"
+				#[pallet::storage]
+				pub type Something<T> = StorageValue<_, u32>;
+
+				#[pallet::event]
+				pub enum Event<T: Config> {}
+
+				#[pallet::storage]
+				pub type Accounts<T> = StorageMap<_, Blake2_128Concat, u32, u32>;
+				"
+			),
+			normalize(
+				"
+				/// Every `#[pallet::storage]` of this pallet, in declaration order.
+				pub type PalletStorages<T> =
+					(crate::pallet::Something<T>, (crate::pallet::Accounts<T>, ()));
+				"
+			),
+		);
+	}
+
+	#[test]
+	fn test_restricted_storages() {
+		assert!(generate(
+			"
+			#[pallet::storage]
+			pub type Something<T> = StorageValue<_, u32>;
+
+			#[pallet::storage]
+			pub(super) type Other<T> = StorageValue<_, u32>;
+			"
+		)
+		.contains("pub(crate) type PalletStorages<T> ="));
+	}
+
+	#[test]
+	fn test_private_storages() {
+		let generated = generate(
+			"
+			#[pallet::storage]
+			pub type Something<T> = StorageValue<_, u32>;
+
+			#[pallet::storage]
+			type Private<T> = StorageValue<_, u32>;
+
+			#[pallet::storage]
+			pub(self) type Other<T> = StorageValue<_, u32>;
+			",
+		);
+
+		assert!(generated.starts_with("::core::compile_error!("));
+		assert!(generated.contains("`Private`, `Other`"));
+		assert!(!generated.contains("`Something`"));
+	}
+
+	#[test]
+	fn test_no_storages() {
+		let generated = generate(
+			"
+			#[pallet::pallet]
+			pub struct Pallet<T>(_);
+			",
+		);
+
+		assert_eq!(
+			generated,
+			normalize(
+				"
+				/// Every `#[pallet::storage]` of this pallet, in declaration order.
+				pub type PalletStorages<T> =
+					<::core::marker::PhantomData<T> as ::change_set::NoStorages>::Storages;
+				"
+			),
+		);
+	}
+
+	#[test]
+	fn test_non_reversible_maps() {
+		assert!(generate(
+			"
+			#[pallet::storage]
+			pub type Reversible<T> = StorageMap<_, Blake2_128Concat, u32, u32>;
+
+			#[pallet::storage]
+			pub type Map<T> = StorageMap<_, Blake2_128, u32, u32>;
+
+			#[pallet::storage]
+			pub type DoubleMap<T> =
+				StorageDoubleMap<_, Blake2_128Concat, u32, Twox128, u32, u32>;
+
+			#[pallet::storage]
+			pub type NMap<T> = StorageNMap<_, (NMapKey<Blake2_256, u32>,), u32>;
+
+			#[pallet::storage]
+			pub type Counted<T> = CountedStorageMap<_, Twox256, u32, u32>;
+
+			#[pallet::storage]
+			pub type Value<T> = StorageValue<_, Blake2_128>;
+			"
+		)
+		.contains(&normalize(
+			"
+			(crate::pallet::Reversible<T>,
+			(::change_set::check_storage::Opaque<crate::pallet::Map<T>>,
+			(::change_set::check_storage::Opaque<crate::pallet::DoubleMap<T>>,
+			(::change_set::check_storage::Opaque<crate::pallet::NMap<T>>,
+			(::change_set::check_storage::Opaque<crate::pallet::Counted<T>>,
+			(crate::pallet::Value<T>, ()))))))
+			"
+		)));
+	}
+
+	#[test]
+	fn test_instanceable_storages() {
+		assert!(generate(
+			"
+			#[pallet::storage]
+			pub type Something<T, I = ()> = StorageValue<_, u32>;
+			"
+		)
+		.contains("pub type PalletStorages<T, I = ()> = (crate::pallet::Something<T, I>, ());"));
+	}
+}